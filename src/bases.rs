@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+
+use crate::uint::{UInt, Widest};
+
+/// The pair of bases searched for double palindromes.
+///
+/// Candidates are enumerated as palindromes in the outer base and checked in the inner base, so
+/// the "decimal" and "binary" names used throughout the search refer to the outer and inner base.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bases {
+    /// The base whose palindromes are enumerated, 10 by default.
+    pub outer: u32,
+    /// The base the candidates are checked in, 2 by default.
+    pub inner: u32,
+}
+
+impl Default for Bases {
+    fn default() -> Self {
+        Self {
+            outer: 10,
+            inner: 2,
+        }
+    }
+}
+
+impl Bases {
+    /// Checks that both bases are at least 2.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.outer < 2 || self.inner < 2 {
+            return Err(format!("bases must be at least 2, got {self:?}"));
+        }
+
+        Ok(())
+    }
+
+    /// The number of bits in an inner digit, if the inner base is a power of two.
+    pub fn inner_digit_bits(&self) -> Option<u32> {
+        self.inner
+            .is_power_of_two()
+            .then(|| self.inner.trailing_zeros())
+    }
+
+    /// The number of lowest inner digits which are fixed once `known_digits` outer digits are,
+    /// i.e. the largest k such that inner^k divides outer^known_digits.
+    pub fn fixed_inner_digits(&self, known_digits: u32) -> u32 {
+        prime_factors(self.inner)
+            .into_iter()
+            .map(|(prime, inner_power)| {
+                multiplicity(self.outer, prime) * known_digits / inner_power
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// The number of bits needed to hold every number searched at `dec_length`, which is the
+    /// largest number with as many inner digits as the largest outer palindrome. None if that's
+    /// more than the widest supported integer.
+    pub fn required_bits(&self, dec_length: u32) -> Option<u32> {
+        let outer = Widest::from(self.outer);
+        let inner = Widest::from(self.inner);
+        let max = outer.checked_pow(dec_length)? - Widest::ONE;
+        let mut max_inner = inner - Widest::ONE;
+        while max_inner < max {
+            max_inner = max_inner
+                .checked_mul(inner)?
+                .checked_add(inner - Widest::ONE)?;
+        }
+
+        Some(max_inner.bits())
+    }
+
+    /// Checks that every number searched at `dec_length` fits in the widest supported integer.
+    pub fn check_fits(&self, dec_length: u32) -> Result<(), String> {
+        if self
+            .required_bits(dec_length)
+            .is_none_or(|bits| bits > Widest::BITS)
+        {
+            return Err(format!(
+                "decimal length {dec_length} doesn't fit in {} bits, the widest supported integer",
+                Widest::BITS
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The part of the outer base which is coprime to two.
+    pub fn odd_outer_factor(&self) -> u32 {
+        self.outer >> self.outer.trailing_zeros()
+    }
+}
+
+/// Everything the search needs to know about the inner digits for a single decimal length.
+#[derive(Clone, Debug)]
+pub struct BaseLayout<T: UInt> {
+    /// The outer base.
+    pub outer: u32,
+    /// The inner base.
+    pub inner: u32,
+    /// Set when the inner base is a power of two, so inner digits are groups of this many bits.
+    pub digit_bits: Option<u32>,
+    /// index is the number of known outer digits.
+    pub fixed_digits: Vec<u32>,
+    /// index is the number of known outer digits. When the outer base is even and the inner base
+    /// is a power of two, the next outer digit's parity decides this (digit, bit in digit) of the
+    /// number.
+    pub parity_bits: Option<Vec<(u32, u32)>>,
+    // index is the digit position, only needed when the inner base isn't a power of two.
+    inner_powers: Vec<T>,
+}
+
+impl<T: UInt> BaseLayout<T> {
+    /// The layout of the numbers of `dec_length` outer digits.
+    pub fn new(bases: Bases, dec_length: u32) -> Self {
+        let digit_bits = bases.inner_digit_bits();
+        let known_digits = 0..=dec_length.div_ceil(2);
+        let parity_bits = digit_bits
+            .filter(|_| bases.outer.is_multiple_of(2))
+            .map(|digit_bits| {
+                known_digits
+                    .clone()
+                    .map(|known_digits| {
+                        let parity_bit = bases.outer.trailing_zeros() * known_digits;
+                        (parity_bit / digit_bits, parity_bit % digit_bits)
+                    })
+                    .collect()
+            });
+
+        let mut inner_powers = vec![];
+        if digit_bits.is_none() {
+            let max = max_number::<T>(bases.outer, dec_length);
+            let mut power = T::ONE;
+            while power <= max {
+                inner_powers.push(power);
+                let Some(next) = power.checked_mul(T::from_u64(bases.inner as u64)) else {
+                    break;
+                };
+                power = next;
+            }
+        }
+
+        Self {
+            outer: bases.outer,
+            inner: bases.inner,
+            digit_bits,
+            fixed_digits: known_digits
+                .map(|known_digits| bases.fixed_inner_digits(known_digits))
+                .collect(),
+            parity_bits,
+            inner_powers,
+        }
+    }
+
+    /// The number of inner digits in `num`.
+    pub fn length(&self, num: T) -> u32 {
+        match self.digit_bits {
+            Some(digit_bits) => num.bits().div_ceil(digit_bits),
+            None => self.inner_powers.partition_point(|&power| power <= num) as u32,
+        }
+    }
+
+    /// The bit length of the inner length, or the length itself when digits aren't bit groups.
+    pub fn length_bits(&self, length: u32) -> u32 {
+        length * self.digit_bits.unwrap_or(1)
+    }
+
+    /// The inner digit at `position`, counted from the least significant one.
+    #[inline]
+    pub fn digit(&self, num: T, position: u32) -> T {
+        match self.digit_bits {
+            Some(digit_bits) => {
+                (num >> (position * digit_bits)) & T::from_u64(self.inner as u64 - 1)
+            }
+            None => (num / self.inner_powers[position as usize]) % T::from_u64(self.inner as u64),
+        }
+    }
+
+    /// Places `digit` at the position mirroring `position` in a number of `length` inner digits.
+    #[inline]
+    pub fn mirror_digit(&self, digit: T, position: u32, length: u32) -> T {
+        match self.digit_bits {
+            Some(digit_bits) => digit << ((length - position - 1) * digit_bits),
+            None => digit * self.inner_powers[(length - position - 1) as usize],
+        }
+    }
+
+    /// The largest value the digits of a palindrome of `length` inner digits can add, when
+    /// `fixed_digits` digits are already fixed on each side.
+    pub fn max_free_digits(&self, length: u32, fixed_digits: u32) -> T {
+        let inner = T::from_u64(self.inner as u64);
+        if fixed_digits * 2 >= length {
+            return T::ZERO;
+        }
+        if fixed_digits == 0 {
+            return max_number(self.inner, length);
+        }
+
+        inner.pow(length - fixed_digits) - inner.pow(fixed_digits)
+    }
+
+    /// Whether `num` is a palindrome of exactly `length` inner digits.
+    pub fn is_palindrome(&self, num: T, length: u32) -> bool {
+        match self.digit_bits {
+            Some(1) => {
+                let leading_zeros = num.leading_zeros();
+                leading_zeros + length == T::BITS && num.reverse_bits() >> leading_zeros == num
+            }
+            _ => {
+                self.length(num) == length
+                    && (0..length / 2).all(|position| {
+                        self.digit(num, position) == self.digit(num, length - position - 1)
+                    })
+            }
+        }
+    }
+}
+
+/// The largest number of `length` digits in `base`.
+pub fn max_number<T: UInt>(base: u32, length: u32) -> T {
+    // Avoids overflowing when base^length doesn't fit.
+    let base = T::from_u64(base as u64);
+    (base.pow(length - 1) - T::ONE) * base + (base - T::ONE)
+}
+
+fn multiplicity(mut num: u32, prime: u32) -> u32 {
+    let mut count = 0;
+    while num.is_multiple_of(prime) {
+        num /= prime;
+        count += 1;
+    }
+
+    count
+}
+
+fn prime_factors(mut num: u32) -> Vec<(u32, u32)> {
+    let mut factors = vec![];
+    let mut prime = 2;
+    while prime * prime <= num {
+        let count = multiplicity(num, prime);
+        if count > 0 {
+            num /= prime.pow(count);
+            factors.push((prime, count));
+        }
+        prime += 1;
+    }
+    if num > 1 {
+        factors.push((num, 1));
+    }
+
+    factors
+}
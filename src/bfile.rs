@@ -0,0 +1,113 @@
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{bases::Bases, uint::Widest, SaveState};
+
+// OEIS sequences of double palindromes start with 0, which the search never enumerates since
+// leading digits can't be zero.
+fn terms(palindromes: &[Widest]) -> BTreeSet<Widest> {
+    palindromes.iter().copied().chain([Widest::ZERO]).collect()
+}
+
+fn num_digits(num: Widest, base: u32) -> u32 {
+    let base = Widest::from(base);
+    let mut digits = 1;
+    let mut rest = num / base;
+    while rest > Widest::ZERO {
+        digits += 1;
+        rest /= base;
+    }
+
+    digits
+}
+
+/// Replaces `path` with the palindromes in OEIS b-file format, "n a(n)" sorted and without
+/// duplicates.
+pub fn write(path: &Path, palindromes: &[Widest]) -> io::Result<()> {
+    let mut temp_name = OsString::from(path.as_os_str());
+    temp_name.push(".tmp");
+    let temp_path = PathBuf::from(temp_name);
+
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    for (index, term) in terms(palindromes).iter().enumerate() {
+        writeln!(writer, "{} {term}", index + 1)?;
+    }
+    writer.into_inner()?.sync_all()?;
+
+    fs::rename(&temp_path, path)
+}
+
+/// Reads the terms of a b-file, checking that they're numbered from 1 and sorted.
+pub fn read(path: &Path) -> Result<Vec<Widest>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut terms: Vec<Widest> = vec![];
+    // Comments are allowed, as in the b-files on the OEIS.
+    for line in contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    {
+        let parsed = line
+            .split_once(' ')
+            .and_then(|(index, term)| Some((index.parse::<usize>().ok()?, term.trim())))
+            .and_then(|(index, term)| Some((index, term.parse::<Widest>().ok()?)));
+        let Some((index, term)) = parsed else {
+            return Err(format!("Invalid line \"{line}\""));
+        };
+        if index != terms.len() + 1 {
+            return Err(format!("Expected term {} but got {index}", terms.len() + 1));
+        }
+        if terms.last().is_some_and(|&last| last >= term) {
+            return Err(format!("Term {index} isn't greater than the one before it"));
+        }
+        terms.push(term);
+    }
+
+    Ok(terms)
+}
+
+/// Checks that the b-file at `path` has exactly the palindromes in `save_state`, before a search
+/// starts updating it.
+///
+/// A b-file is written as soon as something is found but a checkpoint only every so often, so the
+/// b-file may be ahead with palindromes of lengths that aren't done yet. The search finds those
+/// again, so they're only noted in `notes`. A new search can't be behind its b-file though, so it
+/// refuses to overwrite one with any palindromes.
+pub fn check(path: &Path, save_state: &SaveState, notes: &mut Vec<String>) -> Result<(), String> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let in_file: BTreeSet<Widest> = read(path)?.into_iter().collect();
+    let in_checkpoint = terms(&save_state.palindromes_found);
+    if let Some(missing) = in_checkpoint.difference(&in_file).next() {
+        return Err(format!("{missing} is in the checkpoint but not the b-file"));
+    }
+    let is_new = save_state.tasks.is_empty()
+        && save_state.completed_cells.is_empty()
+        && save_state.palindromes_found.is_empty();
+    if let Some(extra) = in_file.difference(&in_checkpoint).next().filter(|_| is_new) {
+        return Err(format!(
+            "{extra} is in the b-file, which a new search would overwrite"
+        ));
+    }
+
+    let Bases { outer, .. } = save_state.bases;
+    for &extra in in_file.difference(&in_checkpoint) {
+        if num_digits(extra, outer) < save_state.dec_length {
+            return Err(format!(
+                "{extra} is in the b-file but not the checkpoint, and its length is done"
+            ));
+        }
+        notes.push(format!(
+            "{extra} is in the b-file but not the checkpoint yet, it will be found again"
+        ));
+    }
+
+    Ok(())
+}
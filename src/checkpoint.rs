@@ -0,0 +1,595 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use crate::{
+    bases::{BaseLayout, Bases},
+    bin_length_range,
+    inspect::level_weights,
+    uint::Widest,
+    SaveState,
+};
+
+/// The current checkpoint format. Version 1 was a bare `SaveState` without a header, version 2
+/// had no shards, version 3 didn't store the share of the search tree below every state and
+/// version 4's checksum didn't cover the header.
+pub const FORMAT_VERSION: u32 = 5;
+
+// Starts every binary checkpoint, followed by the format version.
+const BINARY_MAGIC: &[u8; 8] = b"PALCKPT\0";
+const GZIP_MAGIC: &[u8; 2] = &[0x1f, 0x8b];
+
+/// How a checkpoint is encoded. Loading works out the format by itself.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, ValueEnum)]
+pub enum CheckpointFormat {
+    /// Readable and portable, but large and slow for deep searches.
+    Json,
+    /// Compact bincode.
+    Binary,
+    /// Gzip compressed bincode.
+    Compressed,
+}
+
+impl CheckpointFormat {
+    /// ".bin" files are binary and ".gz" files are compressed, anything else is JSON.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("bin") => Self::Binary,
+            Some("gz") => Self::Compressed,
+            _ => Self::Json,
+        }
+    }
+}
+
+/// Describes the search a checkpoint belongs to, so it isn't resumed by the wrong binary.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format_version: u32,
+    // The version of the crate which wrote the checkpoint.
+    build: String,
+    // The width of the numbers stored in the checkpoint.
+    int_bits: u32,
+    bases: Bases,
+    max_dec_length: Option<u32>,
+    bin_lengths: Option<Vec<u32>>,
+    // FNV-1a hash of the other fields and the serialized state, only of the state before version
+    // 5.
+    checksum: String,
+}
+
+impl Header {
+    fn new(save_state: &SaveState, state: &[u8]) -> Self {
+        let mut header = Self {
+            format_version: FORMAT_VERSION,
+            build: env!("CARGO_PKG_VERSION").to_string(),
+            int_bits: Widest::BITS,
+            bases: save_state.bases,
+            max_dec_length: save_state.max_dec_length,
+            bin_lengths: save_state.bin_lengths.clone(),
+            checksum: String::new(),
+        };
+        header.checksum = format!("{:016x}", header.hash(state));
+        header
+    }
+
+    fn hash(&self, state: &[u8]) -> u64 {
+        if self.format_version < 5 {
+            return fnv1a(state);
+        }
+
+        let fields = format!(
+            "{},{},{},{},{},{:?},{:?};",
+            self.format_version,
+            self.build,
+            self.int_bits,
+            self.bases.outer,
+            self.bases.inner,
+            self.max_dec_length,
+            self.bin_lengths
+        );
+        fnv1a_from(fnv1a(fields.as_bytes()), state)
+    }
+
+    // The checksum covers the header too, so a header which doesn't describe its state was written
+    // that way rather than edited.
+    fn check(
+        &self,
+        save_state: &SaveState,
+        state: &[u8],
+        notes: &mut Vec<String>,
+    ) -> Result<(), String> {
+        let checksum = self.hash(state);
+        if self.int_bits > Widest::BITS {
+            return Err(format!(
+                "it stores {} bit numbers, but this build supports at most {}",
+                self.int_bits,
+                Widest::BITS
+            ));
+        }
+        if self.checksum != format!("{checksum:016x}") {
+            return Err(format!(
+                "its checksum is {}, but its contents hash to {checksum:016x}",
+                self.checksum
+            ));
+        }
+        if self.bases != save_state.bases
+            || self.max_dec_length != save_state.max_dec_length
+            || self.bin_lengths != save_state.bin_lengths
+        {
+            return Err("its header doesn't match the search it contains".to_string());
+        }
+        if self.build != env!("CARGO_PKG_VERSION") {
+            notes.push(format!(
+                "The checkpoint was written by version {} of palindromes, this is version {}",
+                self.build,
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct Checkpoint<'a> {
+    header: Header,
+    state: &'a RawValue,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BinaryCheckpoint {
+    header: Header,
+    // The bincode encoded `SaveState`, kept as bytes so the checksum covers exactly what's stored.
+    state: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct VersionProbe {
+    format_version: u32,
+}
+
+// Only reads as far as needed to tell the format versions apart.
+#[derive(Deserialize)]
+struct Probe<'a> {
+    #[serde(borrow)]
+    header: Option<&'a RawValue>,
+    #[serde(borrow)]
+    state: Option<&'a RawValue>,
+}
+
+/// The 64-bit FNV-1a hash of `bytes`, which checkpoints are checksummed with.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    fnv1a_from(0xcbf29ce484222325, bytes)
+}
+
+// Goes on hashing from `hash`, as if `bytes` followed what it's the hash of.
+fn fnv1a_from(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn invalid_data(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+// The path of the `index`th backup of `path`, where 0 is the checkpoint itself.
+fn backup_path(path: &Path, index: u32) -> PathBuf {
+    if index == 0 {
+        return path.to_path_buf();
+    }
+
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
+}
+
+fn sync_parent(path: &Path) -> io::Result<()> {
+    // Directories can only be opened for syncing on unix.
+    if cfg!(unix) {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Writes `save_state` to `path` without ever leaving a partially written checkpoint behind.
+///
+/// The new checkpoint is written to a temporary file and synced before it replaces the old one,
+/// which is kept as `path.1`, with older ones shifted up to `path.{backups}`.
+pub fn write(
+    path: &Path,
+    save_state: &SaveState,
+    format: CheckpointFormat,
+    backups: u32,
+) -> io::Result<()> {
+    let mut temp_name = OsString::from(path.as_os_str());
+    temp_name.push(".tmp");
+    let temp_path = PathBuf::from(temp_name);
+
+    let file = File::create(&temp_path)?;
+    let file = match format {
+        CheckpointFormat::Json => {
+            let mut writer = BufWriter::new(file);
+            write_json(&mut writer, save_state)?;
+            writer.into_inner()?
+        }
+        CheckpointFormat::Binary => {
+            let mut writer = BufWriter::new(file);
+            write_binary(&mut writer, save_state)?;
+            writer.into_inner()?
+        }
+        CheckpointFormat::Compressed => {
+            let mut writer = GzEncoder::new(BufWriter::new(file), Compression::fast());
+            write_binary(&mut writer, save_state)?;
+            writer.finish()?.into_inner()?
+        }
+    };
+    file.sync_all()?;
+
+    for index in (0..backups).rev() {
+        let from = backup_path(path, index);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, index + 1))?;
+        }
+    }
+    fs::rename(&temp_path, path)?;
+
+    sync_parent(path)
+}
+
+fn write_json(writer: &mut impl Write, save_state: &SaveState) -> io::Result<()> {
+    let state = serde_json::value::to_raw_value(save_state)?;
+    let checkpoint = Checkpoint {
+        header: Header::new(save_state, state.get().as_bytes()),
+        state: &state,
+    };
+    serde_json::to_writer(&mut *writer, &checkpoint)?;
+    writer.flush()
+}
+
+fn write_binary(writer: &mut impl Write, save_state: &SaveState) -> io::Result<()> {
+    let state = bincode::serialize(save_state).map_err(invalid_data)?;
+    let checkpoint = BinaryCheckpoint {
+        header: Header::new(save_state, &state),
+        state,
+    };
+    writer.write_all(BINARY_MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    bincode::serialize_into(&mut *writer, &checkpoint).map_err(invalid_data)?;
+    writer.flush()
+}
+
+fn read(path: &Path, notes: &mut Vec<String>) -> io::Result<SaveState> {
+    let mut contents = fs::read(path)?;
+    if contents.starts_with(GZIP_MAGIC) {
+        let mut decompressed = vec![];
+        GzDecoder::new(&contents[..]).read_to_end(&mut decompressed)?;
+        contents = decompressed;
+    }
+
+    match contents.strip_prefix(BINARY_MAGIC) {
+        Some(contents) => read_binary(contents, notes),
+        None => read_json(&String::from_utf8(contents).map_err(invalid_data)?, notes),
+    }
+}
+
+fn read_binary(contents: &[u8], notes: &mut Vec<String>) -> io::Result<SaveState> {
+    let (version, contents) = contents
+        .split_first_chunk::<4>()
+        .ok_or_else(|| invalid_data("Truncated checkpoint"))?;
+    let format_version = u32::from_le_bytes(*version);
+    // Bincode can't tell which fields an older version lacked, so those need converting to JSON by
+    // the build which wrote them. Version 4 only checksummed less.
+    match format_version {
+        4 => notes.push(format!(
+            "Upgrading checkpoint from format version 4 to {FORMAT_VERSION}"
+        )),
+        FORMAT_VERSION => {}
+        format_version => return Err(unsupported_version(format_version)),
+    }
+
+    let checkpoint: BinaryCheckpoint = bincode::deserialize(contents).map_err(invalid_data)?;
+    let save_state: SaveState = bincode::deserialize(&checkpoint.state).map_err(invalid_data)?;
+    checkpoint
+        .header
+        .check(&save_state, &checkpoint.state, notes)
+        .map_err(|error| invalid_data(format!("Rejected checkpoint, {error}")))?;
+
+    Ok(save_state)
+}
+
+fn read_json(contents: &str, notes: &mut Vec<String>) -> io::Result<SaveState> {
+    let probe: Probe = serde_json::from_str(contents)?;
+    let (Some(header), Some(state)) = (probe.header, probe.state) else {
+        return migrate(1, contents, notes);
+    };
+
+    let version: VersionProbe = serde_json::from_str(header.get())?;
+    match version.format_version {
+        // The differences are the shard, which defaults to None, the shares of the states and what
+        // the checksum covers.
+        2..=4 => notes.push(format!(
+            "Upgrading checkpoint from format version {} to {FORMAT_VERSION}",
+            version.format_version
+        )),
+        FORMAT_VERSION => {}
+        format_version => return migrate(format_version, contents, notes),
+    }
+
+    let header: Header = serde_json::from_str(header.get())?;
+    let mut save_state: SaveState = serde_json::from_str(state.get())?;
+    header
+        .check(&save_state, state.get().as_bytes(), notes)
+        .map_err(|error| invalid_data(format!("Rejected checkpoint, {error}")))?;
+    if version.format_version < 4 {
+        estimate_shares(&mut save_state);
+    }
+
+    Ok(save_state)
+}
+
+// Upgrades a checkpoint written in an older format.
+fn migrate(format_version: u32, contents: &str, notes: &mut Vec<String>) -> io::Result<SaveState> {
+    match format_version {
+        // Fields added since then default to what older binaries did.
+        1 => {
+            notes.push(format!(
+                "Upgrading checkpoint from format version 1 to {FORMAT_VERSION}"
+            ));
+            let mut save_state: SaveState = serde_json::from_str(contents)?;
+            complete_earlier_lengths(&mut save_state);
+            complete_untasked_cells(&mut save_state);
+            estimate_shares(&mut save_state);
+            Ok(save_state)
+        }
+        _ => Err(unsupported_version(format_version)),
+    }
+}
+
+// Version 1 always started from decimal length 1, so without any cells or binary lengths recorded
+// every length before the current one is done.
+fn complete_earlier_lengths(save_state: &mut SaveState) {
+    if !save_state.completed_cells.is_empty() || save_state.bin_lengths.is_some() {
+        return;
+    }
+
+    for dec_length in 1..save_state.dec_length {
+        if save_state.bases.check_fits(dec_length).is_err() {
+            return;
+        }
+        let layout = BaseLayout::<Widest>::new(save_state.bases, dec_length);
+        let (min_bin_length, max_bin_length) = bin_length_range(&layout, dec_length);
+        for bin_length in min_bin_length..=max_bin_length {
+            save_state.complete_cell(dec_length, bin_length);
+        }
+    }
+}
+
+// Version 1 started every binary length of a decimal length at once and didn't record which were
+// done, so with tasks left the binary lengths without one are finished.
+fn complete_untasked_cells(save_state: &mut SaveState) {
+    let dec_length = save_state.dec_length;
+    if save_state.tasks.is_empty() || save_state.bases.check_fits(dec_length).is_err() {
+        return;
+    }
+
+    let layout = BaseLayout::<Widest>::new(save_state.bases, dec_length);
+    let (min_bin_length, max_bin_length) = bin_length_range(&layout, dec_length);
+    for bin_length in min_bin_length..=max_bin_length {
+        if !save_state
+            .tasks
+            .iter()
+            .any(|task| task.bin_length == bin_length)
+        {
+            save_state.complete_cell(dec_length, bin_length);
+        }
+    }
+}
+
+// Versions before 4 didn't store the states' shares, so they're estimated from their levels.
+fn estimate_shares(save_state: &mut SaveState) {
+    let dec_length = save_state.dec_length;
+    if save_state.bases.check_fits(dec_length).is_err() {
+        return;
+    }
+
+    let layout = BaseLayout::<Widest>::new(save_state.bases, dec_length);
+    let weights = level_weights(&layout, dec_length);
+    for state in save_state.tasks.iter_mut().flat_map(|task| &mut task.stack) {
+        state.share = weights[state.level as usize];
+    }
+}
+
+fn unsupported_version(format_version: u32) -> io::Error {
+    invalid_data(format!(
+        "Unsupported checkpoint format version {format_version}, this build reads up to \
+         {FORMAT_VERSION}"
+    ))
+}
+
+/// A checkpoint as loaded, with what it took to load it.
+pub struct Loaded {
+    /// The search to resume.
+    pub save_state: SaveState,
+    /// Anything worth telling about loading it, like upgrading it from an older format or falling
+    /// back to a backup.
+    pub notes: Vec<String>,
+}
+
+/// Loads the checkpoint at `path`, falling back to the newest readable backup if it's missing or
+/// corrupt.
+pub fn load(path: &Path) -> io::Result<Loaded> {
+    let mut notes = vec![];
+    let error = match read(path, &mut notes) {
+        Ok(save_state) => return Ok(Loaded { save_state, notes }),
+        Err(error) => error,
+    };
+
+    let mut index = 1;
+    loop {
+        let backup = backup_path(path, index);
+        if !backup.exists() {
+            return Err(error);
+        }
+
+        let mut backup_notes = vec![];
+        match read(&backup, &mut backup_notes) {
+            Ok(save_state) => {
+                notes.push(format!(
+                    "Couldn't load {}: {error}, using backup {}",
+                    path.display(),
+                    backup.display()
+                ));
+                notes.extend(backup_notes);
+                return Ok(Loaded { save_state, notes });
+            }
+            Err(backup_error) => {
+                notes.push(format!(
+                    "Couldn't load backup {}: {backup_error}",
+                    backup.display()
+                ));
+            }
+        }
+        index += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cell, SaveTask, State};
+
+    // A checkpoint path of its own for every test, since they run in parallel.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("palindromes-{}-{name}", std::process::id()))
+    }
+
+    fn remove(path: &Path, backups: u32) {
+        for index in 0..=backups {
+            let _ = fs::remove_file(backup_path(path, index));
+        }
+    }
+
+    fn save_state() -> SaveState {
+        let shares = [
+            1.2800000000000003e-07,
+            0.1 + 0.2,
+            1.0 / 3.0,
+            f64::MIN_POSITIVE,
+        ];
+        SaveState {
+            completed_cells: vec![Cell {
+                dec_length: 29,
+                bin_length: 94,
+            }],
+            tasks: vec![SaveTask {
+                bin_length: 95,
+                stack: shares
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &share)| State {
+                        current_num: Widest::from(index as u64 + 1) << 90,
+                        bin_num: Widest::MAX >> 1000,
+                        is_odd: Some(index % 2 == 0),
+                        level: index as u32 + 1,
+                        share,
+                    })
+                    .collect(),
+            }],
+            palindromes_found: vec![Widest::from(9u32), Widest::ONE << 97],
+            ..SaveState::new(Bases::default(), 29, Some(31), Some(vec![94, 95]))
+        }
+    }
+
+    fn shares(save_state: &SaveState) -> Vec<u64> {
+        save_state
+            .tasks
+            .iter()
+            .flat_map(|task| &task.stack)
+            .map(|state| state.share.to_bits())
+            .collect()
+    }
+
+    fn assert_same(loaded: &SaveState, expected: &SaveState) {
+        assert_eq!(shares(loaded), shares(expected));
+        assert_eq!(
+            serde_json::to_string(loaded).unwrap(),
+            serde_json::to_string(expected).unwrap()
+        );
+    }
+
+    fn load_quietly(path: &Path) -> SaveState {
+        let loaded = load(path).unwrap();
+        assert!(loaded.notes.is_empty(), "{:?}", loaded.notes);
+        loaded.save_state
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        for format in [
+            CheckpointFormat::Json,
+            CheckpointFormat::Binary,
+            CheckpointFormat::Compressed,
+        ] {
+            let path = temp_path(&format!("round-trip-{format:?}"));
+            write(&path, &save_state(), format, 0).unwrap();
+            assert_same(&load_quietly(&path), &save_state());
+            remove(&path, 0);
+        }
+    }
+
+    #[test]
+    fn converting_keeps_the_shares_exact() {
+        let path = temp_path("convert");
+        let mut current = save_state();
+        for format in [
+            CheckpointFormat::Json,
+            CheckpointFormat::Binary,
+            CheckpointFormat::Compressed,
+            CheckpointFormat::Json,
+        ] {
+            write(&path, &current, format, 0).unwrap();
+            current = load_quietly(&path);
+        }
+        assert_same(&current, &save_state());
+        remove(&path, 0);
+    }
+
+    #[test]
+    fn another_build_is_noted() {
+        let path = temp_path("another-build");
+        let state = serde_json::value::to_raw_value(&save_state()).unwrap();
+        let mut header = Header::new(&save_state(), state.get().as_bytes());
+        header.build = "0.0.1".to_string();
+        header.checksum = format!("{:016x}", header.hash(state.get().as_bytes()));
+        let checkpoint = Checkpoint {
+            header,
+            state: &state,
+        };
+        fs::write(&path, serde_json::to_string(&checkpoint).unwrap()).unwrap();
+
+        let loaded = load(&path).unwrap();
+        assert_same(&loaded.save_state, &save_state());
+        assert_eq!(
+            loaded.notes,
+            [format!(
+                "The checkpoint was written by version 0.0.1 of palindromes, this is version {}",
+                env!("CARGO_PKG_VERSION")
+            )]
+        );
+        remove(&path, 0);
+    }
+}
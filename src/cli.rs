@@ -0,0 +1,232 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use palindromes::{checkpoint::CheckpointFormat, events::OutputFormat};
+
+/// Finds numbers which are palindromes in two bases.
+///
+/// A search exits with status 0 once its range of decimal lengths is done, or 3 if it was
+/// interrupted first. Every command exits with status 1 when it fails, printing why, e.g. on a
+/// checkpoint it can't load, and verify also when it finds problems. Invalid arguments exit with
+/// status 2.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Starts a new search.
+    Search(SearchArgs),
+    /// Continues a search from a checkpoint.
+    Resume(ResumeArgs),
+    /// Checks the palindromes in a checkpoint or a search's output, independently of the search.
+    ///
+    /// Reports every listed number which isn't a palindrome in both bases or is listed twice, and
+    /// every palindrome missing from a completely searched decimal length.
+    Verify(VerifyArgs),
+    /// Rewrites a checkpoint in another format, e.g. to export a binary one as JSON.
+    ConvertCheckpoint {
+        input: PathBuf,
+        output: PathBuf,
+        /// Defaults to the format implied by the output's extension.
+        #[arg(long, value_enum)]
+        format: Option<CheckpointFormat>,
+    },
+    /// Splits a checkpoint into shards which can be resumed on different machines.
+    ///
+    /// The shards are written next to the checkpoint as "name.shardN.ext", and only search its
+    /// current decimal length.
+    SplitCheckpoint {
+        checkpoint: PathBuf,
+        #[arg(long)]
+        shards: u32,
+    },
+    /// Merges all the shards split from one checkpoint back into it.
+    MergeCheckpoints {
+        output: PathBuf,
+        #[arg(required = true)]
+        shards: Vec<PathBuf>,
+    },
+    /// Splits a decimal length into work units, printed as one JSON object per line.
+    ExportUnits(ExportUnitsArgs),
+    /// Runs the work units in a file, or "-" for stdin, printing a JSON result for each.
+    RunUnits {
+        units: PathBuf,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// Hands the work units in a file out to workers over TCP, until they're all done.
+    Coordinate {
+        units: PathBuf,
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        listen: String,
+        /// Appends every unit's result to this file as a JSON line.
+        #[arg(long)]
+        results: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+        /// Prints connections and finished units.
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Searches the work units handed out by a coordinator until it has none left.
+    Work {
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        connect: String,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// Summarizes a checkpoint and estimates how much of its decimal length is searched.
+    #[command(alias = "inspect-checkpoint")]
+    Inspect { checkpoint: PathBuf },
+    /// Times a search over a fixed range of decimal lengths, without checkpoints.
+    Bench(BenchArgs),
+}
+
+#[derive(Args)]
+pub struct SearchArgs {
+    /// The first decimal length to search.
+    #[arg(long, default_value_t = 1)]
+    pub from: u32,
+    /// The last decimal length to search, unbounded if not given.
+    #[arg(long)]
+    pub to: Option<u32>,
+    /// Searches only this decimal length.
+    #[arg(
+        long,
+        conflicts_with_all = ["from", "to"],
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub length: Option<u32>,
+    /// Searches only these binary lengths in every decimal length, e.g. "97,98".
+    #[arg(long, value_delimiter = ',')]
+    pub bin_lengths: Option<Vec<u32>>,
+    /// The base palindromes are enumerated in.
+    #[arg(long, default_value_t = 10)]
+    pub outer: u32,
+    /// The base palindromes are checked in.
+    #[arg(long, default_value_t = 2)]
+    pub inner: u32,
+    /// Where to save the search when it's interrupted or done.
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+    /// Keeps every palindrome found in this file, in OEIS b-file format.
+    #[arg(long)]
+    pub b_file: Option<PathBuf>,
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Args)]
+pub struct ResumeArgs {
+    pub checkpoint: PathBuf,
+    /// Overrides the last decimal length saved in the checkpoint.
+    #[arg(long)]
+    pub to: Option<u32>,
+    /// Keeps every palindrome found in this file, in OEIS b-file format. It has to agree with the
+    /// checkpoint if it exists.
+    #[arg(long)]
+    pub b_file: Option<PathBuf>,
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// A checkpoint, or with --results a search's output as "time: number" lines or JSON lines.
+    pub file: PathBuf,
+    /// Reads the file as a search's output, e.g. one of the results directory.
+    #[arg(long)]
+    pub results: bool,
+    /// The base of a search's output the palindromes were enumerated in, checkpoints have theirs.
+    #[arg(long, default_value_t = 10)]
+    pub outer: u32,
+    /// The base of a search's output the palindromes were checked in.
+    #[arg(long, default_value_t = 2)]
+    pub inner: u32,
+    /// Takes the decimal lengths up to this one as completely searched, besides the ones the
+    /// output or checkpoint says were finished.
+    #[arg(long)]
+    pub complete_to: Option<u32>,
+    /// Looks for missing palindromes by trying every palindrome of the complete decimal lengths up
+    /// to this one, which takes about ten times as long for every two more digits.
+    #[arg(long, default_value_t = 12)]
+    pub enumerate_to: u32,
+}
+
+#[derive(Args)]
+pub struct ExportUnitsArgs {
+    /// The decimal length to split.
+    #[arg(long)]
+    pub length: u32,
+    /// Only exports units of these binary lengths.
+    #[arg(long, value_delimiter = ',')]
+    pub bin_lengths: Option<Vec<u32>>,
+    #[arg(long, default_value_t = 10)]
+    pub outer: u32,
+    #[arg(long, default_value_t = 2)]
+    pub inner: u32,
+    /// The number of leading outer digits units are split by.
+    #[arg(long, default_value_t = 2)]
+    pub prefix_digits: u32,
+    /// The number of units for every binary length.
+    #[arg(long, default_value_t = 10)]
+    pub units: u64,
+}
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// The last decimal length to search.
+    #[arg(long, default_value_t = 30)]
+    pub to: u32,
+    #[arg(long, default_value_t = 10)]
+    pub outer: u32,
+    #[arg(long, default_value_t = 2)]
+    pub inner: u32,
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+/// Settings which only affect how a search runs, not what it finds.
+#[derive(Args)]
+pub struct RunArgs {
+    /// The memory budget for lookup tables in MiB, defaults to all available memory.
+    #[arg(long)]
+    pub memory: Option<u64>,
+    /// The number of worker threads, defaults to one per core.
+    #[arg(long)]
+    pub threads: Option<usize>,
+    /// Seconds between checkpoints while searching, 0 to only write one when stopping.
+    #[arg(long, default_value_t = 600)]
+    pub checkpoint_interval: u64,
+    /// Overrides the checkpoint format implied by its extension.
+    #[arg(long, value_enum)]
+    pub checkpoint_format: Option<CheckpointFormat>,
+    /// The number of previous checkpoints to keep as backups.
+    #[arg(long, default_value_t = 3)]
+    pub backups: u32,
+    /// Branches above this level are spawned as separate tasks.
+    #[arg(long, default_value_t = 4)]
+    pub spawn_depth: u32,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+    /// Seconds between progress reports with node counts and an estimated completion time, 0 for
+    /// none.
+    #[arg(long, default_value_t = 0)]
+    pub progress: u64,
+    /// Prints how many nodes were visited and cut by each kind of pruning at every level, once
+    /// each decimal length is done.
+    #[arg(long)]
+    pub stats: bool,
+    /// Prints the palindromes of each decimal length in ascending order once it's done, instead
+    /// of as they're found.
+    #[arg(long)]
+    pub sorted: bool,
+    /// Prints table generation and progress details.
+    #[arg(short, long)]
+    pub verbose: bool,
+}
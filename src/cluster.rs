@@ -0,0 +1,523 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bases::Bases,
+    events::{self, Event},
+    progress::Progress,
+    uint::{self, with_uint, UInt, Widest},
+    unit::{unit_save_state, UnitResult, UnitSearcher, WorkUnit},
+    SearchConfig, State,
+};
+
+// How often workers report progress, which also tells the coordinator they're alive.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+// How often workers check for new palindromes to send.
+const FOUND_INTERVAL: Duration = Duration::from_millis(100);
+// Workers which stay silent for this long are assumed gone, and their units are reissued.
+const WORKER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A unit handed to a worker, with the stack it was stopped at if it was started before.
+#[derive(Clone, Serialize, Deserialize)]
+struct Assignment {
+    id: u64,
+    unit: WorkUnit,
+    stack: Option<Vec<State<Widest>>>,
+}
+
+/// The messages of the protocol, sent as one JSON object per line.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    // Worker to coordinator.
+    Ready,
+    Found {
+        id: u64,
+        #[serde(with = "uint::hex")]
+        value: Widest,
+    },
+    Progress {
+        id: Option<u64>,
+        nodes: u64,
+    },
+    Finished {
+        id: u64,
+        result: UnitResult,
+    },
+    Unfinished {
+        id: u64,
+        stack: Vec<State<Widest>>,
+        result: UnitResult,
+    },
+    // Coordinator to worker.
+    Assign(Assignment),
+    Done,
+}
+
+fn send(writer: &mut impl Write, message: &Message) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, message)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+fn receive(reader: &mut impl BufRead) -> io::Result<Message> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(serde_json::from_str(&line)?)
+}
+
+struct Queue {
+    pending: VecDeque<Assignment>,
+    // The connection each unit was handed to.
+    in_flight: HashMap<u64, (u64, Assignment)>,
+    // What stopped units found before being reissued from their stacks.
+    partial: HashMap<u64, (Vec<Widest>, u64)>,
+    unfinished_units: usize,
+    connections: usize,
+}
+
+fn write_result(writer: &mut impl Write, result: &UnitResult) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, result)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+struct Coordinator<'a> {
+    queue: Mutex<Queue>,
+    changed: Condvar,
+    found: Mutex<HashSet<Widest>>,
+    results: Option<Mutex<BufWriter<File>>>,
+    // The first error writing the results, after which no more units are handed out.
+    error: Mutex<Option<io::Error>>,
+    config: &'a SearchConfig,
+    start_time: Instant,
+}
+
+impl Coordinator<'_> {
+    fn report(&self, event: Event) {
+        events::report(event, self.config, self.start_time);
+    }
+
+    fn report_found(&self, unit: &WorkUnit, value: Widest) {
+        if self.found.lock().unwrap().insert(value) {
+            let Bases { outer, inner } = unit.bases;
+            self.report(Event::found(
+                value,
+                outer,
+                inner,
+                unit.dec_length,
+                unit.bin_length,
+            ));
+        }
+    }
+
+    fn is_failed(&self) -> bool {
+        self.error.lock().unwrap().is_some() || self.config.report_error.get().is_some()
+    }
+
+    // Why coordinating failed, once all the connections are done.
+    fn failure(&self) -> io::Result<()> {
+        if let Some(error) = self.error.lock().unwrap().take() {
+            return Err(error);
+        }
+        match self.config.report_error.get() {
+            Some(error) => Err(io::Error::other(format!("Error reporting events: {error}"))),
+            None => Ok(()),
+        }
+    }
+
+    // The unit `id` if it's the connection's, rather than reissued to another one.
+    fn owned_unit(&self, connection: u64, id: u64) -> Option<WorkUnit> {
+        match self.queue.lock().unwrap().in_flight.get(&id) {
+            Some((owner, assignment)) if *owner == connection => Some(assignment.unit.clone()),
+            _ => None,
+        }
+    }
+
+    // Blocks until there's a unit for the worker, or returns None once all units are done or
+    // coordinating failed.
+    fn next_assignment(&self, connection: u64) -> Option<Assignment> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if self.is_failed() {
+                return None;
+            }
+            if let Some(assignment) = queue.pending.pop_front() {
+                queue
+                    .in_flight
+                    .insert(assignment.id, (connection, assignment.clone()));
+                return Some(assignment);
+            }
+            if queue.unfinished_units == 0 {
+                return None;
+            }
+            queue = self.changed.wait(queue).unwrap();
+        }
+    }
+
+    fn finish(&self, id: u64, mut result: UnitResult) {
+        for &value in &result.palindromes {
+            self.report_found(&result.unit, value);
+        }
+
+        let mut queue = self.queue.lock().unwrap();
+        queue.in_flight.remove(&id);
+        if let Some((palindromes, nodes)) = queue.partial.remove(&id) {
+            result.palindromes.extend(palindromes);
+            result.palindromes.sort_unstable();
+            result.palindromes.dedup();
+            result.nodes += nodes;
+        }
+        queue.unfinished_units -= 1;
+        self.report(Event::UnitFinished {
+            id,
+            left: queue.unfinished_units,
+        });
+        if let Some(results) = &self.results {
+            if let Err(error) = write_result(&mut *results.lock().unwrap(), &result) {
+                self.error.lock().unwrap().get_or_insert(error);
+            }
+        }
+        self.changed.notify_all();
+    }
+
+    fn requeue(&self, id: u64, stack: Vec<State<Widest>>, result: UnitResult) {
+        for &value in &result.palindromes {
+            self.report_found(&result.unit, value);
+        }
+
+        let mut queue = self.queue.lock().unwrap();
+        let Some((_, assignment)) = queue.in_flight.remove(&id) else {
+            return;
+        };
+        self.report(Event::UnitReissued { id, error: None });
+        let partial = queue.partial.entry(id).or_default();
+        partial.0.extend(result.palindromes);
+        partial.1 += result.nodes;
+        queue.pending.push_front(Assignment {
+            stack: Some(stack),
+            ..assignment
+        });
+        self.changed.notify_all();
+    }
+
+    // Hands the units of a lost worker to the others, from wherever they were last stopped.
+    fn disconnect(&self, connection: u64, error: io::Error) {
+        let mut queue = self.queue.lock().unwrap();
+        let lost: Vec<u64> = queue
+            .in_flight
+            .iter()
+            .filter(|(_, &(owner, _))| owner == connection)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in lost {
+            let (_, assignment) = queue.in_flight.remove(&id).unwrap();
+            self.report(Event::UnitReissued {
+                id,
+                error: Some(error.to_string()),
+            });
+            queue.pending.push_front(assignment);
+        }
+        queue.connections -= 1;
+        self.changed.notify_all();
+    }
+
+    fn serve(&self, connection: u64, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(WORKER_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        loop {
+            match receive(&mut reader)? {
+                Message::Ready => match self.next_assignment(connection) {
+                    Some(assignment) => send(&mut writer, &Message::Assign(assignment))?,
+                    None => return send(&mut writer, &Message::Done),
+                },
+                Message::Found { id, value } => {
+                    if let Some(unit) = self.owned_unit(connection, id) {
+                        self.report_found(&unit, value);
+                    }
+                }
+                Message::Finished { id, result } if self.owned_unit(connection, id).is_some() => {
+                    self.finish(id, result)
+                }
+                Message::Unfinished { id, stack, result }
+                    if self.owned_unit(connection, id).is_some() =>
+                {
+                    self.requeue(id, stack, result)
+                }
+                // Progress only keeps the connection alive, and reissued units are ignored.
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Hands `units` out to the workers connecting to `listener` until all of them are done.
+///
+/// Found palindromes are reported as they come in, along with connections and finished units, to
+/// the sink of `config` or in its format, and every unit's result is appended to `results` as a
+/// JSON line. Fails once the workers are done if writing the results or reporting failed, which
+/// stops it from handing out more units.
+pub fn coordinate(
+    units: Vec<WorkUnit>,
+    listener: TcpListener,
+    results: Option<&Path>,
+    config: &SearchConfig,
+) -> io::Result<()> {
+    for unit in &units {
+        unit.validate()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+    }
+
+    let coordinator = Coordinator {
+        queue: Mutex::new(Queue {
+            unfinished_units: units.len(),
+            pending: units
+                .into_iter()
+                .enumerate()
+                .map(|(id, unit)| Assignment {
+                    id: id as u64,
+                    unit,
+                    stack: None,
+                })
+                .collect(),
+            in_flight: HashMap::new(),
+            partial: HashMap::new(),
+            connections: 0,
+        }),
+        changed: Condvar::new(),
+        found: Mutex::new(HashSet::new()),
+        results: results
+            .map(|path| {
+                File::options()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map(|file| Mutex::new(BufWriter::new(file)))
+            })
+            .transpose()?,
+        error: Mutex::new(None),
+        config,
+        start_time: Instant::now(),
+    };
+    let coordinator = &coordinator;
+    coordinator.report(Event::Listening {
+        address: listener.local_addr()?,
+    });
+
+    // Accepting is polled, so the listener can stop once everything is done.
+    listener.set_nonblocking(true)?;
+    std::thread::scope(|scope| {
+        let mut next_connection = 0;
+        loop {
+            {
+                let queue = coordinator.queue.lock().unwrap();
+                if (queue.unfinished_units == 0 || coordinator.is_failed())
+                    && queue.connections == 0
+                {
+                    return coordinator.failure();
+                }
+            }
+
+            let stream = match listener.accept() {
+                Ok((stream, peer)) => {
+                    coordinator.report(Event::WorkerConnected { peer });
+                    stream
+                }
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+            stream.set_nonblocking(false)?;
+            let connection = next_connection;
+            next_connection += 1;
+            coordinator.queue.lock().unwrap().connections += 1;
+            scope.spawn(move || match coordinator.serve(connection, stream) {
+                Ok(()) => {
+                    coordinator.queue.lock().unwrap().connections -= 1;
+                    coordinator.changed.notify_all();
+                }
+                Err(error) => coordinator.disconnect(connection, error),
+            });
+        }
+    })
+}
+
+// Set while a worker searches a unit, when interrupting it should send back its stacks.
+static SEARCHING: AtomicBool = AtomicBool::new(false);
+
+/// Whether an interrupted worker has a unit to hand back before it exits.
+pub fn is_searching() -> bool {
+    SEARCHING.load(Ordering::Relaxed)
+}
+
+/// Connects to a coordinator and searches the units it hands out until it has none left.
+///
+/// Returns false if the worker was interrupted by setting `SearchConfig::cancel`, after sending
+/// back the stacks it had left. Fails the same way, once the stacks are sent back, if its events
+/// couldn't be reported.
+pub fn work(address: impl ToSocketAddrs, config: &SearchConfig) -> io::Result<bool> {
+    let stream = TcpStream::connect(address)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let writer = Mutex::new(BufWriter::new(stream));
+
+    let worker = Worker {
+        writer,
+        current: Mutex::new(None),
+        progress: Progress::default(),
+        stopped: AtomicBool::new(false),
+    };
+    let completed = std::thread::scope(|scope| {
+        let worker = &worker;
+        let heartbeat = scope.spawn(move || worker.heartbeat());
+        let completed = worker.run(&mut reader, config);
+        worker.stopped.store(true, Ordering::Relaxed);
+        heartbeat.thread().unpark();
+        completed
+    })?;
+
+    match config.report_error.get() {
+        Some(error) => Err(io::Error::other(format!("Error reporting events: {error}"))),
+        None => Ok(completed),
+    }
+}
+
+struct Worker {
+    writer: Mutex<BufWriter<TcpStream>>,
+    // The unit being searched.
+    current: Mutex<Option<u64>>,
+    progress: Progress,
+    stopped: AtomicBool,
+}
+
+impl Worker {
+    fn send(&self, message: &Message) -> io::Result<()> {
+        send(&mut *self.writer.lock().unwrap(), message)
+    }
+
+    fn heartbeat(&self) {
+        loop {
+            std::thread::park_timeout(PROGRESS_INTERVAL);
+            if self.stopped.load(Ordering::Relaxed) {
+                return;
+            }
+            let message = Message::Progress {
+                id: *self.current.lock().unwrap(),
+                nodes: self.progress.nodes.load(Ordering::Relaxed),
+            };
+            if self.send(&message).is_err() {
+                return;
+            }
+        }
+    }
+    fn run(&self, reader: &mut impl BufRead, config: &SearchConfig) -> io::Result<bool> {
+        self.send(&Message::Ready)?;
+        let mut next = receive(reader)?;
+        loop {
+            let Message::Assign(assignment) = next else {
+                return Ok(true);
+            };
+
+            let unit = &assignment.unit;
+            next = with_uint!(unit.bases.required_bits(unit.dec_length), T => {
+                self.run_length::<T>(assignment, reader, config)?
+            });
+            if config.is_stopped() {
+                return Ok(false);
+            }
+        }
+    }
+
+    // Searches assignments of the same decimal length with the same tables, and returns the first
+    // message which isn't one of them.
+    fn run_length<T: UInt>(
+        &self,
+        mut assignment: Assignment,
+        reader: &mut impl BufRead,
+        config: &SearchConfig,
+    ) -> io::Result<Message> {
+        let searcher =
+            UnitSearcher::<T>::new(assignment.unit.bases, assignment.unit.dec_length, config);
+        loop {
+            let unit_start = Instant::now();
+            let id = assignment.id;
+            let save_state = Mutex::new(unit_save_state(&assignment.unit));
+            self.progress.nodes.store(0, Ordering::Relaxed);
+            *self.current.lock().unwrap() = Some(id);
+            SEARCHING.store(true, Ordering::Relaxed);
+            std::thread::scope(|scope| -> io::Result<()> {
+                let search = scope.spawn(|| {
+                    searcher.search(
+                        &assignment.unit,
+                        assignment.stack.as_deref(),
+                        config,
+                        &save_state,
+                        &self.progress,
+                    )
+                });
+                // Palindromes are streamed as they're found, in case the worker is lost later.
+                let mut sent = 0;
+                loop {
+                    let finished = search.is_finished();
+                    let found = save_state.lock().unwrap().palindromes_found[sent..].to_vec();
+                    for value in found {
+                        self.send(&Message::Found { id, value })?;
+                        sent += 1;
+                    }
+                    if finished {
+                        return Ok(());
+                    }
+                    std::thread::sleep(FOUND_INTERVAL);
+                }
+            })?;
+            SEARCHING.store(false, Ordering::Relaxed);
+            *self.current.lock().unwrap() = None;
+
+            let save_state = save_state.into_inner().unwrap();
+            let mut palindromes = save_state.palindromes_found;
+            palindromes.sort_unstable();
+            let result = UnitResult {
+                unit: assignment.unit,
+                palindromes,
+                nodes: self.progress.nodes.load(Ordering::Relaxed),
+                seconds: unit_start.elapsed().as_secs_f32(),
+            };
+            if config.is_stopped() {
+                let stack = save_state
+                    .tasks
+                    .into_iter()
+                    .flat_map(|task| task.stack)
+                    .collect();
+                self.send(&Message::Unfinished { id, stack, result })?;
+                return Ok(Message::Done);
+            }
+            self.send(&Message::Finished { id, result })?;
+
+            self.send(&Message::Ready)?;
+            let next = receive(reader)?;
+            match next {
+                Message::Assign(next) if searcher.can_search(&next.unit) => {
+                    assignment = next;
+                }
+                next => return Ok(next),
+            }
+        }
+    }
+}
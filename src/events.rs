@@ -0,0 +1,360 @@
+use std::{net::SocketAddr, path::PathBuf, time::Instant};
+
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+
+use crate::{
+    progress::LevelCounts,
+    sink::{JsonLinesSink, PalindromeSink, StdoutSink},
+    uint::{UInt, Widest},
+    SearchConfig,
+};
+
+/// How events are printed.
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// "time: number" lines.
+    #[default]
+    Text,
+    /// One JSON object per line for every event, including the ones only shown when verbose.
+    Json,
+}
+
+/// Something that happened during a search, printed as a line of text or as a JSON object, or
+/// handed to a `PalindromeSink`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A palindrome was found.
+    Found {
+        /// Written in base 10, whatever the bases are.
+        #[serde(serialize_with = "decimal")]
+        value: Widest,
+        /// Its digits in the outer base.
+        outer: String,
+        /// Its digits in the inner base.
+        inner: String,
+        /// Its length in outer digits.
+        dec_length: u32,
+        /// Its length in inner digits.
+        bin_length: u32,
+    },
+    /// A decimal length had none of the wanted binary lengths, so it wasn't searched.
+    LengthSkipped {
+        /// The decimal length skipped.
+        dec_length: u32,
+    },
+    /// The search of a decimal length started, or resumed from a checkpoint.
+    LengthStarted {
+        /// The decimal length started.
+        dec_length: u32,
+    },
+    /// Every wanted binary length of a decimal length was searched.
+    LengthFinished {
+        /// The decimal length finished.
+        dec_length: u32,
+        /// The states expanded searching it in this session.
+        nodes: u64,
+    },
+    /// How many digits the lookup tables of a decimal length would cover, memory permitting.
+    TablesPlanned {
+        /// The decimal length they're for.
+        dec_length: u32,
+        /// The most low outer digits a table covers.
+        max_digits: u32,
+    },
+    /// A lookup table is being generated.
+    TableStarted {
+        /// The decimal length it's for.
+        dec_length: u32,
+        /// The number of low outer digits it covers.
+        num_digits: u32,
+    },
+    /// A lookup table was generated.
+    TableGenerated {
+        /// The decimal length it's for.
+        dec_length: u32,
+        /// The number of low outer digits it covers.
+        num_digits: u32,
+        /// Its size in bytes.
+        size: usize,
+        /// The fraction of set bits, the lower the more the table prunes.
+        saturation: f64,
+        /// Outer numbers per bit of the table.
+        factor: f64,
+    },
+    /// The remainder tables were set up.
+    RemainderTablesGenerated {
+        /// The decimal length they're for.
+        dec_length: u32,
+        /// The highest power of the outer base's odd factor used as a modulus.
+        max_digits: u32,
+        /// The most the tables take once they're all filled in, which happens as they're used.
+        size: u64,
+    },
+    /// The remainder tables cut branches while searching a decimal length.
+    RemainderTablesPruned {
+        /// The decimal length searched.
+        dec_length: u32,
+        /// The branches cut at every level, from the root.
+        levels: Vec<u64>,
+    },
+    /// A checkpoint was written.
+    CheckpointWritten {
+        /// Where it was written.
+        path: PathBuf,
+        /// The tasks left in it.
+        tasks: usize,
+    },
+    /// How far the current decimal length is, reported every `SearchConfig::progress_interval`.
+    Progress {
+        /// The decimal length being searched.
+        dec_length: u32,
+        /// The states expanded so far in this session.
+        nodes: u64,
+        /// The states expanded per second since the previous report.
+        nodes_per_second: f64,
+        /// The branches cut because the binary and decimal numbers can't meet anymore.
+        pruned_interval: u64,
+        /// The branches cut by the lookup tables.
+        pruned_table: u64,
+        /// Nodes expanded at every level since the previous report.
+        levels: Vec<u64>,
+        /// The fraction of the decimal length searched by this session.
+        done: f64,
+        /// The estimated time left at the rate of the last minute, once something was finished in
+        /// it.
+        eta_seconds: Option<f64>,
+    },
+    /// What happened at every level of a finished decimal length, when `SearchConfig::stats` is
+    /// set.
+    LevelStats {
+        /// The decimal length finished.
+        dec_length: u32,
+        /// The counts of every level, from the root.
+        levels: Vec<LevelStats>,
+    },
+    /// A coordinator is waiting for workers.
+    Listening {
+        /// Where workers connect to.
+        address: SocketAddr,
+    },
+    /// A worker connected to the coordinator.
+    WorkerConnected {
+        /// Where it connected from.
+        peer: SocketAddr,
+    },
+    /// A worker finished a unit handed out by the coordinator.
+    UnitFinished {
+        /// The unit's position in the coordinator's units.
+        id: u64,
+        /// The units not finished yet.
+        left: usize,
+    },
+    /// A unit is handed out again from where its worker was stopped.
+    UnitReissued {
+        /// The unit's position in the coordinator's units.
+        id: u64,
+        /// Why the worker was lost, None if it handed the unit back.
+        error: Option<String>,
+    },
+    /// Something went wrong which the search could carry on from, like failing to write a
+    /// checkpoint which the next one makes up for.
+    Warning {
+        /// What went wrong.
+        message: String,
+    },
+}
+
+/// The counts of one level of a finished decimal length.
+#[derive(Clone, Debug, Serialize)]
+pub struct LevelStats {
+    /// The number of outer digits fixed at each end.
+    pub level: u32,
+    /// The digits covered by the level's lookup table, if it has one.
+    pub table_digits: Option<u32>,
+    /// What happened to the candidates at the level.
+    #[serde(flatten)]
+    pub counts: LevelCounts,
+}
+
+fn decimal<S: Serializer>(num: &Widest, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(num)
+}
+
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+impl Event {
+    /// A palindrome found, with its digits written out in both bases.
+    pub fn found<T: UInt>(
+        num: T,
+        outer: u32,
+        inner: u32,
+        dec_length: u32,
+        bin_length: u32,
+    ) -> Self {
+        Self::Found {
+            value: num.to_widest(),
+            outer: num.to_string_radix(outer),
+            inner: num.to_string_radix(inner),
+            dec_length,
+            bin_length,
+        }
+    }
+
+    /// The event as a line of text output.
+    pub fn to_text(&self, elapsed: f32) -> String {
+        match self {
+            Self::Found { value, .. } => format!("{elapsed:.4}: {value}"),
+            Self::LengthSkipped { dec_length } => {
+                format!("Skipping decimal length {dec_length}, no binary lengths left to search")
+            }
+            Self::LengthStarted { dec_length } => {
+                format!("{elapsed:.4}: Starting decimal length: {dec_length}")
+            }
+            Self::LengthFinished { dec_length, nodes } => {
+                format!(
+                    "{elapsed:.4}: Finished decimal length {dec_length}, expanded {nodes} nodes"
+                )
+            }
+            Self::TablesPlanned {
+                dec_length,
+                max_digits,
+            } => format!(
+                "{elapsed:.4}: Tables for decimal length {dec_length} cover up to {max_digits} \
+                 digits"
+            ),
+            Self::TableStarted {
+                dec_length,
+                num_digits,
+            } => format!(
+                "{elapsed:.4}: Generating table for decimal length {dec_length}, num_digits: \
+                 {num_digits}"
+            ),
+            Self::TableGenerated {
+                dec_length,
+                num_digits,
+                size,
+                saturation,
+                factor,
+            } => format!(
+                "{elapsed:.4}: Generated table for decimal length {dec_length}, num_digits: \
+                 {num_digits}, size: {size}, factor: {factor}, saturation: {saturation:.4}"
+            ),
+            Self::RemainderTablesGenerated {
+                dec_length,
+                max_digits,
+                size,
+            } => format!(
+                "{elapsed:.4}: Generated remainder tables for decimal length {dec_length}, \
+                 max_digits: {max_digits}, size: {size}"
+            ),
+            Self::RemainderTablesPruned { dec_length, levels } => {
+                let mut text =
+                    format!("{elapsed:.4}: Remainder tables pruned decimal length {dec_length}");
+                for (level, pruned) in levels.iter().enumerate() {
+                    if *pruned != 0 {
+                        text += &format!("\n{pruned} branches at level {level}");
+                    }
+                }
+                text
+            }
+            Self::CheckpointWritten { path, tasks } => format!(
+                "{elapsed:.4}: Wrote checkpoint {} with {tasks} tasks",
+                path.display()
+            ),
+            Self::Progress {
+                dec_length,
+                nodes,
+                nodes_per_second,
+                pruned_interval,
+                pruned_table,
+                levels,
+                done,
+                eta_seconds,
+            } => {
+                let eta = eta_seconds.map_or("unknown".to_string(), format_duration);
+                let levels: Vec<String> = levels
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &count)| count > 0)
+                    .map(|(level, count)| format!("{level}:{count}"))
+                    .collect();
+                format!(
+                    "{elapsed:.4}: Decimal length {dec_length} {:.2}% done, ETA {eta}, {nodes} \
+                     nodes at {nodes_per_second:.0}/s, pruned {pruned_interval} by interval and \
+                     {pruned_table} by table, levels {}",
+                    done * 100.0,
+                    levels.join(" ")
+                )
+            }
+            Self::LevelStats { dec_length, levels } => {
+                let mut text = format!(
+                    "{elapsed:.4}: Statistics for decimal length {dec_length}\n{:>5} {:>6} {:>16} \
+                     {:>16} {:>16} {:>16} {:>16}",
+                    "level",
+                    "table",
+                    "visited",
+                    "cut by bound",
+                    "cut by table",
+                    "cut by remainder",
+                    "leaves checked"
+                );
+                for stats in levels {
+                    let table = stats
+                        .table_digits
+                        .map_or("-".to_string(), |digits| digits.to_string());
+                    let counts = &stats.counts;
+                    text += &format!(
+                        "\n{:>5} {table:>6} {:>16} {:>16} {:>16} {:>16} {:>16}",
+                        stats.level,
+                        counts.visited,
+                        counts.cut_bound,
+                        counts.cut_table,
+                        counts.cut_remainder,
+                        counts.leaves_checked
+                    );
+                }
+                text
+            }
+            Self::Listening { address } => format!("Listening on {address}"),
+            Self::WorkerConnected { peer } => format!("Worker connected from {peer}"),
+            Self::UnitFinished { id, left } => {
+                format!("{elapsed:.4}: Finished unit {id}, {left} left")
+            }
+            Self::UnitReissued { id, error: None } => {
+                format!("{elapsed:.4}: Reissuing unit {id} from where its worker stopped")
+            }
+            Self::UnitReissued {
+                id,
+                error: Some(error),
+            } => format!("{elapsed:.4}: Reissuing unit {id} after losing its worker: {error}"),
+            Self::Warning { message } => format!("{elapsed:.4}: {message}"),
+        }
+    }
+}
+
+/// Hands `event` to the search's sink, or prints it in the configured format if it has none. An
+/// error doing so stops the search.
+pub fn report(event: Event, config: &SearchConfig, start_time: Instant) {
+    let elapsed = start_time.elapsed().as_secs_f32();
+    let reported = match (&config.sink, config.format) {
+        (Some(sink), _) => sink.report(&event, elapsed),
+        (None, OutputFormat::Text) => StdoutSink {
+            verbose: config.verbose,
+        }
+        .report(&event, elapsed),
+        (None, OutputFormat::Json) => JsonLinesSink::stdout().report(&event, elapsed),
+    };
+    if let Err(error) = reported {
+        // Later errors are most likely the same one again.
+        let _ = config.report_error.set(error.to_string());
+    }
+}
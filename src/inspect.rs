@@ -0,0 +1,205 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    bases::{BaseLayout, Bases},
+    bin_length_range, initial_state, shard,
+    uint::{UInt, Widest},
+    SaveState,
+};
+
+/// How far the search of one binary length of the current decimal length is.
+pub enum BinLengthStatus {
+    /// Started, with states left to search.
+    Searching {
+        /// The tasks left.
+        tasks: usize,
+        /// The states in the tasks' stacks.
+        states: usize,
+        /// The share of the binary length's search tree already searched.
+        explored: f64,
+    },
+    /// Searched to the end, or not wanted.
+    Done,
+    /// Not started yet.
+    NotStarted,
+}
+
+/// A summary of a save state, with an estimate of how much of its current decimal length is
+/// already searched.
+pub struct Summary {
+    /// The bases searched.
+    pub bases: Bases,
+    /// The decimal length being searched.
+    pub dec_length: u32,
+    /// The last decimal length to search, unbounded if None.
+    pub max_dec_length: Option<u32>,
+    /// The number of palindromes found so far.
+    pub palindromes_found: usize,
+    /// Only these binary lengths are searched, all of them if None.
+    pub bin_lengths: Option<Vec<u32>>,
+    /// Which shard the save state is, if it was split from a larger search.
+    pub shard: Option<String>,
+    /// The number of completed (decimal length, binary length) pairs.
+    pub completed_cells: usize,
+    /// The number of tasks left.
+    pub tasks: usize,
+    /// The number of states in the tasks' stacks.
+    pub states: usize,
+    /// How many states are left at each level.
+    pub states_per_level: BTreeMap<u32, usize>,
+    /// Every wanted binary length of the current decimal length, empty if its numbers don't fit
+    /// in the widest integer.
+    pub bin_length_statuses: Vec<(u32, BinLengthStatus)>,
+}
+
+impl Summary {
+    /// The share of the current decimal length already searched, averaged over its binary
+    /// lengths.
+    pub fn explored(&self) -> Option<f64> {
+        if self.bin_length_statuses.is_empty() {
+            return None;
+        }
+
+        let explored_sum: f64 = self
+            .bin_length_statuses
+            .iter()
+            .map(|(_, status)| match status {
+                BinLengthStatus::Searching { explored, .. } => *explored,
+                BinLengthStatus::Done => 1.0,
+                BinLengthStatus::NotStarted => 0.0,
+            })
+            .sum();
+        Some(explored_sum / self.bin_length_statuses.len() as f64)
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "bases: {} and {}", self.bases.outer, self.bases.inner)?;
+        writeln!(f, "decimal length: {}", self.dec_length)?;
+        if let Some(max_dec_length) = self.max_dec_length {
+            writeln!(f, "last decimal length: {max_dec_length}")?;
+        }
+        writeln!(f, "palindromes found: {}", self.palindromes_found)?;
+        if let Some(bin_lengths) = &self.bin_lengths {
+            writeln!(f, "binary lengths: {bin_lengths:?}")?;
+        }
+        if let Some(shard) = &self.shard {
+            writeln!(f, "shard: {shard}")?;
+        }
+        writeln!(f, "completed cells: {}", self.completed_cells)?;
+        writeln!(f, "tasks: {}", self.tasks)?;
+        write!(f, "states: {}", self.states)?;
+
+        if !self.states_per_level.is_empty() {
+            write!(f, "\nstates per level:")?;
+            for (level, states) in &self.states_per_level {
+                write!(f, "\n  {level}: {states}")?;
+            }
+        }
+
+        let Some(explored) = self.explored() else {
+            return Ok(());
+        };
+        write!(f, "\nbinary lengths of decimal length {}:", self.dec_length)?;
+        for (bin_length, status) in &self.bin_length_statuses {
+            match status {
+                BinLengthStatus::Searching {
+                    tasks,
+                    states,
+                    explored,
+                } => write!(
+                    f,
+                    "\n  {bin_length}: {tasks} tasks, {states} states, {:.2}% explored",
+                    explored * 100.0
+                )?,
+                BinLengthStatus::Done => write!(f, "\n  {bin_length}: done")?,
+                BinLengthStatus::NotStarted => write!(f, "\n  {bin_length}: not started")?,
+            }
+        }
+        write!(f, "\nestimated explored: {:.2}%", explored * 100.0)
+    }
+}
+
+/// Summarizes a save state.
+pub fn inspect(save_state: &SaveState) -> Summary {
+    let dec_length = save_state.dec_length;
+    let mut summary = Summary {
+        bases: save_state.bases,
+        dec_length,
+        max_dec_length: save_state.max_dec_length,
+        palindromes_found: save_state.palindromes_found.len(),
+        bin_lengths: save_state.bin_lengths.clone(),
+        shard: save_state.shard.as_ref().map(shard::describe),
+        completed_cells: save_state.completed_cells.len(),
+        tasks: save_state.tasks.len(),
+        states: save_state.tasks.iter().map(|task| task.stack.len()).sum(),
+        states_per_level: BTreeMap::new(),
+        bin_length_statuses: vec![],
+    };
+
+    // The tasks, states and the share of the search tree still below them, of every binary length.
+    let mut per_bin_length: BTreeMap<u32, (usize, usize, f64)> = BTreeMap::new();
+    for task in &save_state.tasks {
+        let (tasks, states, remaining) = per_bin_length.entry(task.bin_length).or_default();
+        *tasks += 1;
+        *states += task.stack.len();
+        for state in &task.stack {
+            *remaining += state.share;
+            *summary.states_per_level.entry(state.level).or_default() += 1;
+        }
+    }
+
+    if save_state.bases.check_fits(dec_length).is_err() {
+        return summary;
+    }
+    let layout = BaseLayout::<Widest>::new(save_state.bases, dec_length);
+
+    // Binary lengths without stacks are either done or not started yet.
+    let (min_bin_length, max_bin_length) = bin_length_range(&layout, dec_length);
+    for bin_length in min_bin_length..=max_bin_length {
+        if save_state
+            .bin_lengths
+            .as_ref()
+            .is_some_and(|bin_lengths| !bin_lengths.contains(&bin_length))
+        {
+            continue;
+        }
+
+        let status = match per_bin_length.get(&bin_length) {
+            Some(&(tasks, states, remaining)) => BinLengthStatus::Searching {
+                tasks,
+                states,
+                explored: (1.0 - remaining).clamp(0.0, 1.0),
+            },
+            None if !save_state.is_cell_wanted(dec_length, bin_length) => BinLengthStatus::Done,
+            None => BinLengthStatus::NotStarted,
+        };
+        summary.bin_length_statuses.push((bin_length, status));
+    }
+
+    summary
+}
+
+/// The share of a binary length's search tree below a single state at every level, assuming
+/// nothing is pruned. Every level fixes one outer digit at each end, and only half the digits
+/// once their parity is decided by the inner digits.
+pub fn level_weights<T: UInt>(layout: &BaseLayout<T>, dec_length: u32) -> Vec<f64> {
+    let first_digits = match initial_state(layout).is_odd {
+        Some(true) => layout.outer / 2,
+        _ => layout.outer - 1,
+    };
+    // The parity is nearly always known past the first digit, as the leading inner digits are.
+    let digits = match layout.parity_bits {
+        Some(_) => layout.outer / 2,
+        None => layout.outer,
+    };
+    let mut weights = vec![1.0];
+    let mut weight = 1.0 / first_digits as f64;
+    for _ in 0..dec_length.div_ceil(2) {
+        weights.push(weight);
+        weight /= digits as f64;
+    }
+
+    weights
+}
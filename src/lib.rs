@@ -0,0 +1,1186 @@
+//! Finds numbers which are palindromes in two bases, e.g. <https://oeis.org/A007632> for bases
+//! 10 and 2.
+//!
+//! Numbers are enumerated by fixing the digits of the outer base from both ends inwards, and
+//! branches whose numbers can't be palindromes in the inner base are pruned by comparing their
+//! bounds, by `LookupTable`s of the possible low inner digits and by `RemainderTables`.
+//!
+//! ```no_run
+//! use palindromes::{bases::Bases, run_search, SaveState, SearchConfig};
+//!
+//! // Prints the palindromes in bases 10 and 2 with up to 20 decimal digits.
+//! let save_state = SaveState::new(Bases::default(), 1, Some(20), None);
+//! let completed = run_search(save_state, &SearchConfig::default()).expect("Invalid search");
+//! assert!(completed);
+//! ```
+#![allow(clippy::too_many_arguments)]
+#![warn(missing_docs)]
+use rayon::Scope;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, OnceLock,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+use sysinfo::{MemoryRefreshKind, RefreshKind};
+
+/// The two bases and how numbers of a given length are laid out in them.
+pub mod bases;
+/// Results files in OEIS b-file format.
+pub mod bfile;
+/// Saving and loading `SaveState`s.
+pub mod checkpoint;
+/// Handing work units out to workers over TCP.
+pub mod cluster;
+/// What a search reports while running.
+pub mod events;
+/// Summaries of checkpoints.
+pub mod inspect;
+/// Tables pruning numbers by their possible low inner digits.
+pub mod par_bitmap_table;
+/// Node counts and completion estimates.
+pub mod progress;
+/// Tables pruning numbers by their remainders.
+pub mod remainder_table;
+/// Splitting checkpoints into shards and merging them back.
+pub mod shard;
+/// Where a search's events go, e.g. to store palindromes.
+pub mod sink;
+/// The unsigned integers searches run on.
+pub mod uint;
+/// Parts of a search which can be run on their own.
+pub mod unit;
+/// Checking results without the search's code.
+pub mod verify;
+
+use bases::{max_number, BaseLayout, Bases};
+use checkpoint::CheckpointFormat;
+use events::{Event, LevelStats, OutputFormat};
+pub use par_bitmap_table::{LevelTable, LookupTable};
+use progress::{Counts, Progress};
+pub use remainder_table::RemainderTables;
+use shard::Shard;
+use sink::{ChannelSink, PalindromeSink};
+use uint::{with_uint, UInt, Widest};
+
+/// The number of significant bits in a number.
+pub trait Bits {
+    /// The position of the highest set bit plus one, 0 for 0.
+    fn bits(&self) -> u32;
+}
+
+impl Bits for usize {
+    fn bits(&self) -> u32 {
+        Self::BITS - self.leading_zeros()
+    }
+}
+
+/// A node of the search tree: a number with its outermost digits in the outer base fixed.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct State<T: UInt> {
+    /// The number with only its fixed outer digits set.
+    #[serde(with = "uint::hex")]
+    pub current_num: T,
+    /// The inner digits mirroring the ones fixed in `current_num`.
+    #[serde(with = "uint::hex")]
+    pub bin_num: T,
+    /// The parity the next outer digit must have, if known.
+    pub is_odd: Option<bool>,
+    /// The number of outer digits fixed at each end.
+    pub level: u32,
+    /// The share of its binary length's search tree below this state, where the whole tree is 1.
+    /// Missing from checkpoints before format version 4, which fill it in when loaded.
+    #[serde(default)]
+    pub share: f64,
+}
+
+impl<T: UInt> State<T> {
+    /// The same state with its numbers widened to `Widest`, for saving it.
+    pub fn to_widest(&self) -> State<Widest> {
+        State {
+            current_num: self.current_num.to_widest(),
+            bin_num: self.bin_num.to_widest(),
+            is_odd: self.is_odd,
+            level: self.level,
+            share: self.share,
+        }
+    }
+
+    /// The state saved by `to_widest`, narrowed back.
+    pub fn from_widest(state: &State<Widest>) -> Self {
+        Self {
+            current_num: T::from_widest(state.current_num),
+            bin_num: T::from_widest(state.bin_num),
+            is_odd: state.is_odd,
+            level: state.level,
+            share: state.share,
+        }
+    }
+}
+
+/// Searches below the states in `stack` for palindromes of one binary length, spawning the
+/// branches above `config.spawn_depth` as separate tasks on `scope`.
+///
+/// Palindromes are added to `save_state`, and if the search is stopped or checkpointed the states
+/// left are saved there as a task.
+pub(crate) fn find_palindrome_recursive<'scope, T: UInt>(
+    mut stack: Vec<State<T>>,
+    dec_length: u32,
+    bin_length: u32,
+    layout: &'scope BaseLayout<T>,
+    digit_cache: &'scope [Vec<T>],
+    max_dec_cache: &'scope [T],
+    max_bin_cache: &'scope [T],
+    lookup_table: &'scope LookupTable,
+    remainder_tables: &'scope RemainderTables,
+    config: &'scope SearchConfig,
+    start_time: Instant,
+    scope: &Scope<'scope>,
+    save_state: &'scope Mutex<SaveState>,
+    progress: &'scope Progress,
+) {
+    let bin_bits = layout.length_bits(bin_length);
+    let mut remainder_pruned = vec![0u64; digit_cache.len()];
+    let mut counts = Counts::new(digit_cache.len() + 1);
+    loop {
+        if config.is_stopped() || config.checkpoint_due.load(Ordering::Relaxed) {
+            save_state.lock().unwrap().tasks.push(SaveTask {
+                bin_length,
+                stack: stack.iter().map(State::to_widest).collect(),
+            });
+            break;
+        }
+        let Some(state) = stack.pop() else { break };
+        counts.expand(state.level, progress);
+
+        let current_num = state.current_num;
+        let bin_num = state.bin_num;
+        let level = state.level;
+
+        // The leading digit can't be zero.
+        let min_digit = (level == 0) as u32;
+        let digits = match state.is_odd {
+            Some(true) => (1..layout.outer).step_by(2),
+            Some(false) => (min_digit * 2..layout.outer).step_by(2),
+            None => (min_digit..layout.outer).step_by(1),
+        };
+
+        if (state.level + 1) * 2 >= dec_length {
+            counts.levels[level as usize + 1].leaves_checked += digits.len() as u64;
+            counts.done += state.share;
+            for digit in digits {
+                let new_num = state.current_num + digit_cache[level as usize][digit as usize];
+                if layout.is_palindrome(new_num, bin_length) {
+                    record_palindrome(
+                        new_num, dec_length, bin_length, layout, config, start_time, save_state,
+                    );
+                }
+            }
+
+            continue;
+        }
+
+        let max_bin_add = max_bin_cache[level as usize];
+        let max_dec_add = max_dec_cache[level as usize];
+        let new_fixed_digits =
+            layout.fixed_digits[level as usize]..layout.fixed_digits[level as usize + 1];
+        let child_share = state.share / digits.len() as f64;
+
+        for digit in digits {
+            let new_num = current_num + digit_cache[level as usize][digit as usize];
+            let mut new_bin_num = bin_num;
+            for position in new_fixed_digits.clone() {
+                new_bin_num +=
+                    layout.mirror_digit(layout.digit(new_num, position), position, bin_length);
+            }
+
+            let new_max_dec = new_num + max_dec_add;
+
+            if new_bin_num + max_bin_add < new_num || new_max_dec < new_bin_num {
+                counts.levels[level as usize + 1].cut_bound += 1;
+                counts.done += child_share;
+                continue;
+            }
+
+            // The remaining pruning works on bits, so it needs the inner digits to be bit groups.
+            let is_odd = if let Some(digit_bits) = layout.digit_bits {
+                let msb_set_bits = (bin_bits as i32) - ((new_max_dec ^ new_num).bits() as i32);
+                let msb_set_digit_bits = if digit_bits == 1 {
+                    msb_set_bits
+                } else {
+                    msb_set_bits - msb_set_bits.rem_euclid(digit_bits as i32)
+                };
+
+                if !lookup_table.lookup(new_num, msb_set_digit_bits, level + 1, bin_bits) {
+                    counts.levels[level as usize + 1].cut_table += 1;
+                    counts.done += child_share;
+                    continue;
+                }
+
+                if !remainder_tables.lookup(new_num, msb_set_bits, level + 1, bin_bits) {
+                    remainder_pruned[level as usize + 1] += 1;
+                    counts.levels[level as usize + 1].cut_remainder += 1;
+                    counts.done += child_share;
+                    continue;
+                }
+
+                layout.parity_bits.as_ref().and_then(|parity_bits| {
+                    let (parity_digit, digit_bit) = parity_bits[level as usize + 1];
+                    let parity_bit = parity_digit * digit_bits + digit_bit;
+                    let mirrored_bit = (bin_length - parity_digit - 1) * digit_bits + digit_bit;
+                    if (bin_bits as i32) - msb_set_bits > mirrored_bit as i32 {
+                        return None;
+                    }
+
+                    let wanted_digit = (new_max_dec >> mirrored_bit).low_u64() & 1;
+                    Some((new_num >> parity_bit).low_u64() & 1 != wanted_digit)
+                })
+            } else {
+                None
+            };
+
+            if level < config.spawn_depth {
+                scope.spawn(move |scope| {
+                    find_palindrome_recursive(
+                        vec![State {
+                            current_num: new_num,
+                            bin_num: new_bin_num,
+                            is_odd,
+                            level: level + 1,
+                            share: child_share,
+                        }],
+                        dec_length,
+                        bin_length,
+                        layout,
+                        digit_cache,
+                        max_dec_cache,
+                        max_bin_cache,
+                        lookup_table,
+                        remainder_tables,
+                        config,
+                        start_time,
+                        scope,
+                        save_state,
+                        progress,
+                    );
+                })
+            } else {
+                stack.push(State {
+                    current_num: new_num,
+                    bin_num: new_bin_num,
+                    is_odd,
+                    level: level + 1,
+                    share: child_share,
+                });
+            }
+        }
+    }
+
+    remainder_tables.record_pruned(&remainder_pruned);
+    counts.flush(progress);
+}
+
+// Keeps a palindrome in the save state, so it's checkpointed, and reports it unless it's held back
+// to be reported in order.
+fn record_palindrome<T: UInt>(
+    num: T,
+    dec_length: u32,
+    bin_length: u32,
+    layout: &BaseLayout<T>,
+    config: &SearchConfig,
+    start_time: Instant,
+    save_state: &Mutex<SaveState>,
+) {
+    if config.report && !config.sorted {
+        events::report(
+            Event::found(num, layout.outer, layout.inner, dec_length, bin_length),
+            config,
+            start_time,
+        );
+    }
+    save_state
+        .lock()
+        .unwrap()
+        .palindromes_found
+        .push(num.to_widest());
+    if let Some(b_file) = &config.b_file {
+        write_b_file(b_file, config, start_time, save_state);
+    }
+}
+
+// Rewrites the b-file without holding up the workers on the save state. A failed write is only a
+// warning, since the next one or the last one at the end of the search catches up.
+fn write_b_file(
+    b_file: &Path,
+    config: &SearchConfig,
+    start_time: Instant,
+    save_state: &Mutex<SaveState>,
+) {
+    // Copying under the b-file's lock means a later write never has fewer palindromes.
+    let _writing = config.b_file_lock.lock().unwrap();
+    let palindromes = save_state.lock().unwrap().palindromes_found.clone();
+    if let Err(error) = bfile::write(b_file, &palindromes) {
+        events::report(
+            Event::Warning {
+                message: format!("Error writing b-file {}: {error}", b_file.display()),
+            },
+            config,
+            start_time,
+        );
+    }
+}
+
+/// Searches every decimal length from the save state's up to `max_dec_length`, picking up its
+/// tasks first.
+///
+/// Returns true once every decimal length up to `max_dec_length` is searched, or false if the
+/// search was interrupted. Without a `max_dec_length`, it returns an error once it gets to a
+/// decimal length too wide for the widest supported integer.
+pub fn find_palindrome(
+    save_state: &Mutex<SaveState>,
+    config: &SearchConfig,
+    start_time: Instant,
+) -> Result<bool, String> {
+    loop {
+        let (dec_length, bases, max_dec_length) = {
+            let save_state = save_state.lock().unwrap();
+            (
+                save_state.dec_length,
+                save_state.bases,
+                save_state.max_dec_length,
+            )
+        };
+        if max_dec_length.is_some_and(|max_dec_length| dec_length > max_dec_length) {
+            return Ok(true);
+        }
+        if config.is_stopped() {
+            return Ok(false);
+        }
+        bases
+            .check_fits(dec_length)
+            .map_err(|error| format!("Stopped after decimal length {}: {error}", dec_length - 1))?;
+
+        with_uint!(bases.required_bits(dec_length), T => {
+            find_palindrome_length::<T>(dec_length, bases, save_state, config, start_time)
+        });
+
+        if save_state.lock().unwrap().tasks.is_empty() {
+            save_state.lock().unwrap().dec_length += 1;
+        } else {
+            return Ok(false);
+        }
+    }
+}
+
+fn find_palindrome_length<T: UInt>(
+    dec_length: u32,
+    bases: Bases,
+    save_state: &Mutex<SaveState>,
+    config: &SearchConfig,
+    start_time: Instant,
+) {
+    let layout = BaseLayout::<T>::new(bases, dec_length);
+    let (min_bin_length, max_bin_length) = bin_length_range(&layout, dec_length);
+    let (new_bin_lengths, has_tasks): (Vec<u32>, bool) = {
+        let save_state = save_state.lock().unwrap();
+        (
+            (min_bin_length..=max_bin_length)
+                .filter(|&bin_length| save_state.is_cell_wanted(dec_length, bin_length))
+                .collect(),
+            !save_state.tasks.is_empty(),
+        )
+    };
+    if new_bin_lengths.is_empty() && !has_tasks {
+        events::report(Event::LengthSkipped { dec_length }, config, start_time);
+        return;
+    }
+
+    let digit_cache = get_digit_cache(dec_length, bases.outer);
+    let max_dec_cache = get_max_cache::<T>(dec_length, bases.outer);
+
+    events::report(Event::LengthStarted { dec_length }, config, start_time);
+
+    let max_bin_caches: Vec<_> = (min_bin_length..=max_bin_length)
+        .map(|bin_length| get_max_bin_cache(&layout, bin_length, digit_cache.len()))
+        .collect();
+
+    let (lookup_table, remainder_tables) = generate_tables(
+        dec_length,
+        bases,
+        &layout,
+        &digit_cache,
+        min_bin_length,
+        max_bin_length,
+        config,
+        start_time,
+    );
+
+    let progress = Progress::new(digit_cache.len() + 1, config.stats);
+    let finished = AtomicBool::new(false);
+    let mut total = None;
+    std::thread::scope(|threads| {
+        let _finished = SetOnDrop(&finished);
+        // Every pass runs until the tasks are done, or until they are stopped for a checkpoint.
+        loop {
+            let mut searched_bin_lengths: Vec<u32> = vec![];
+            rayon::scope(|scope| {
+                let existing_tasks = &mut save_state.lock().unwrap().tasks;
+                let tasks: Vec<(u32, Vec<State<T>>)> = if existing_tasks.is_empty() {
+                    new_bin_lengths
+                        .iter()
+                        .map(|&bin_length| (bin_length, vec![initial_state(&layout)]))
+                        .collect()
+                } else {
+                    std::mem::take(existing_tasks)
+                        .into_iter()
+                        .map(|task| {
+                            (
+                                task.bin_length,
+                                task.stack.iter().map(State::from_widest).collect(),
+                            )
+                        })
+                        .collect()
+                };
+                searched_bin_lengths = tasks.iter().map(|&(bin_length, ..)| bin_length).collect();
+                searched_bin_lengths.sort_unstable();
+                searched_bin_lengths.dedup();
+
+                // The estimate starts from what's left when this session reaches the length.
+                let remaining: f64 = tasks
+                    .iter()
+                    .flat_map(|(_, stack)| stack)
+                    .map(|state| state.share)
+                    .sum();
+                let total = *total.get_or_insert_with(|| {
+                    if let Some(interval) = config.progress_interval {
+                        let (finished, progress) = (&finished, &progress);
+                        threads.spawn(move || {
+                            progress::report_until(
+                                finished, progress, dec_length, remaining, interval, config,
+                                start_time,
+                            )
+                        });
+                    }
+                    remaining
+                });
+                progress.set_done(total - remaining);
+
+                for (bin_length, stack) in tasks {
+                    let layout_ref = &layout;
+                    let digit_cache_ref = &digit_cache;
+                    let max_dec_cache_ref = &max_dec_cache;
+                    let lookup_table_ref = &lookup_table;
+                    let remainder_tables_ref = &remainder_tables;
+                    let progress_ref = &progress;
+                    let max_bin_cache_ref = &max_bin_caches[(bin_length - min_bin_length) as usize];
+                    scope.spawn(move |scope| {
+                        find_palindrome_recursive(
+                            stack,
+                            dec_length,
+                            bin_length,
+                            layout_ref,
+                            digit_cache_ref,
+                            max_dec_cache_ref,
+                            max_bin_cache_ref,
+                            lookup_table_ref,
+                            remainder_tables_ref,
+                            config,
+                            start_time,
+                            scope,
+                            save_state,
+                            progress_ref,
+                        );
+                    });
+                }
+            });
+
+            // Cells which left no tasks behind were searched to the end.
+            let mut save_state = save_state.lock().unwrap();
+            for bin_length in searched_bin_lengths {
+                if !save_state
+                    .tasks
+                    .iter()
+                    .any(|task| task.bin_length == bin_length)
+                {
+                    save_state.complete_cell(dec_length, bin_length);
+                }
+            }
+
+            if save_state.tasks.is_empty() || config.is_stopped() {
+                break;
+            }
+
+            // All the workers are stopped and their stacks saved, so this is a consistent state.
+            config.checkpoint_due.store(false, Ordering::Relaxed);
+            if let Some(checkpoint) = &config.checkpoint {
+                // A failed write leaves the previous checkpoint in place, so the search can go on.
+                if let Err(error) = checkpoint::write(
+                    checkpoint,
+                    &save_state,
+                    config.checkpoint_format(checkpoint),
+                    config.backups,
+                ) {
+                    events::report(
+                        Event::Warning {
+                            message: format!(
+                                "Error writing checkpoint {}: {error}",
+                                checkpoint.display()
+                            ),
+                        },
+                        config,
+                        start_time,
+                    );
+                } else {
+                    events::report(
+                        Event::CheckpointWritten {
+                            path: checkpoint.clone(),
+                            tasks: save_state.tasks.len(),
+                        },
+                        config,
+                        start_time,
+                    );
+                }
+            }
+        }
+    });
+
+    // An interrupted length isn't finished, it's picked up again when resuming.
+    if save_state.lock().unwrap().tasks.is_empty() {
+        // This includes the palindromes found before resuming.
+        if config.sorted {
+            let min_num = match dec_length {
+                1 => Widest::ZERO,
+                _ => max_number::<Widest>(bases.outer, dec_length - 1),
+            };
+            let max_num = max_number::<T>(bases.outer, dec_length).to_widest();
+            let mut found: Vec<Widest> = save_state
+                .lock()
+                .unwrap()
+                .palindromes_found
+                .iter()
+                .copied()
+                .filter(|&num| num > min_num && num <= max_num)
+                .collect();
+            found.sort_unstable();
+            found.dedup();
+            for num in found {
+                let num = T::from_widest(num);
+                events::report(
+                    Event::found(
+                        num,
+                        layout.outer,
+                        layout.inner,
+                        dec_length,
+                        layout.length(num),
+                    ),
+                    config,
+                    start_time,
+                );
+            }
+        }
+        events::report(
+            Event::LengthFinished {
+                dec_length,
+                nodes: progress.nodes.load(Ordering::Relaxed),
+            },
+            config,
+            start_time,
+        );
+        if let Some(stats) = progress.stats() {
+            let levels = stats
+                .into_iter()
+                .enumerate()
+                .map(|(level, counts)| LevelStats {
+                    level: level as u32,
+                    table_digits: lookup_table
+                        .sub_caches
+                        .get(level)
+                        .is_some_and(Option::is_some)
+                        .then(|| (digit_cache.len() - level) as u32),
+                    counts,
+                })
+                .collect();
+            events::report(Event::LevelStats { dec_length, levels }, config, start_time);
+        }
+    }
+    let levels = remainder_tables.pruned();
+    if levels.iter().any(|&pruned| pruned != 0) {
+        events::report(
+            Event::RemainderTablesPruned { dec_length, levels },
+            config,
+            start_time,
+        );
+    }
+}
+
+/// The range of lengths in the inner base of numbers with `dec_length` outer digits.
+pub fn bin_length_range<T: UInt>(layout: &BaseLayout<T>, dec_length: u32) -> (u32, u32) {
+    let max_bin_length = layout.length(max_number::<T>(layout.outer, dec_length));
+    let min_bin_length = if dec_length == 1 {
+        1
+    } else {
+        layout.length(T::from_u64(layout.outer as u64).pow(dec_length - 1) + T::ONE)
+    };
+
+    (min_bin_length, max_bin_length)
+}
+
+/// Builds as many pruning tables as fit in the memory budget.
+pub(crate) fn generate_tables<T: UInt>(
+    dec_length: u32,
+    bases: Bases,
+    layout: &BaseLayout<T>,
+    digit_cache: &[Vec<T>],
+    min_bin_length: u32,
+    max_bin_length: u32,
+    config: &SearchConfig,
+    start_time: Instant,
+) -> (LookupTable, RemainderTables) {
+    let mut lookup_table = LookupTable::new(digit_cache, layout);
+    let mut remaining_memory = config.memory_budget.unwrap_or_else(|| {
+        sysinfo::System::new_with_specifics(
+            RefreshKind::nothing().with_memory(MemoryRefreshKind::nothing().with_ram()),
+        )
+        .available_memory()
+    });
+    // Every decimal digit fixes one binary digit, leaving log2(5) unknown bits for the table.
+    let unknown_bits = (bases.odd_outer_factor() as f64).log2();
+    let desired_max_cache_digits =
+        (dec_length as f64 * unknown_bits / (2f64 * unknown_bits + 1f64) / 2f64).floor() as u32;
+    let max_cache_digits = if LookupTable::is_supported(layout) {
+        (remaining_memory * 8)
+            .ilog(bases.outer as u64)
+            .min(desired_max_cache_digits)
+            .min(config.max_table_digits.unwrap_or(u32::MAX))
+    } else {
+        0
+    };
+    events::report(
+        Event::TablesPlanned {
+            dec_length,
+            max_digits: desired_max_cache_digits,
+        },
+        config,
+        start_time,
+    );
+    for num_digits in (2..=max_cache_digits).rev() {
+        let Some((downscale_factor, size)) = LevelTable::calculate_memory_requirements(
+            num_digits,
+            bases.outer,
+            remaining_memory,
+            config.min_downscale_factor,
+        ) else {
+            continue;
+        };
+        remaining_memory -= size;
+        events::report(
+            Event::TableStarted {
+                dec_length,
+                num_digits,
+            },
+            config,
+            start_time,
+        );
+        if lookup_table.generate(num_digits, downscale_factor, digit_cache) {
+            let level = digit_cache.len() - num_digits as usize;
+            let instance = lookup_table.sub_caches[level].as_ref().unwrap();
+            events::report(
+                Event::TableGenerated {
+                    dec_length,
+                    num_digits,
+                    size: instance.size(),
+                    saturation: instance.saturation(),
+                    factor: (bases.outer as u64).pow(num_digits) as f64
+                        / (instance.size() * 8) as f64,
+                },
+                config,
+                start_time,
+            );
+        }
+    }
+
+    // Remainder tables are built from pairs of binary digits.
+    let num_bin_lengths = max_bin_length - min_bin_length + 1;
+    let (max_remainder_digits, remainder_size) = if layout.digit_bits == Some(1) {
+        RemainderTables::calculate_memory_requirements(
+            num_bin_lengths,
+            bases.odd_outer_factor(),
+            remaining_memory,
+        )
+        .unwrap_or((0, 0))
+    } else {
+        (0, 0)
+    };
+    let remainder_tables = RemainderTables::new(
+        digit_cache.len(),
+        min_bin_length,
+        max_bin_length,
+        bases.odd_outer_factor(),
+        max_remainder_digits,
+    );
+    events::report(
+        Event::RemainderTablesGenerated {
+            dec_length,
+            max_digits: max_remainder_digits,
+            size: remainder_size,
+        },
+        config,
+        start_time,
+    );
+
+    (lookup_table, remainder_tables)
+}
+
+/// The root of every binary length's search tree, with no digits fixed.
+pub(crate) fn initial_state<T: UInt>(layout: &BaseLayout<T>) -> State<T> {
+    State {
+        current_num: T::ZERO,
+        bin_num: T::ZERO,
+        // Binary palindromes are odd, and so is the first digit in an even base.
+        is_odd: (layout.digit_bits == Some(1) && layout.outer.is_multiple_of(2)).then_some(true),
+        level: 0,
+        share: 1.0,
+    }
+}
+
+/// The most the free outer digits can add at every level, for numbers of `length` digits.
+pub(crate) fn get_max_cache<T: UInt>(length: u32, base: u32) -> Vec<T> {
+    let cache_length = length.div_ceil(2);
+    let base = T::from_u64(base as u64);
+    (1..cache_length)
+        .map(|i| base.pow(length - i) - base.pow(i))
+        .collect()
+}
+
+/// The most the free inner digits can add at every level, for numbers of `bin_length` digits.
+pub(crate) fn get_max_bin_cache<T: UInt>(
+    layout: &BaseLayout<T>,
+    bin_length: u32,
+    cache_length: usize,
+) -> Vec<T> {
+    (1..cache_length)
+        .map(|i| layout.max_free_digits(bin_length, layout.fixed_digits[i]))
+        .collect()
+}
+
+/// The value every outer digit adds at every level, set at both ends of a `dec_length` number.
+pub(crate) fn get_digit_cache<T: UInt>(dec_length: u32, base: u32) -> Vec<Vec<T>> {
+    let cache_length = dec_length.div_ceil(2);
+    (0..cache_length)
+        .map(|i| {
+            let j = dec_length - i - 1;
+            let mut entry = T::from_u64(base as u64).pow(i);
+            if i != j {
+                entry += T::from_u64(base as u64).pow(j);
+            }
+
+            (0..base)
+                .map(|digit| entry * T::from_u64(digit as u64))
+                .collect()
+        })
+        .collect()
+}
+
+/// The states left to search in one binary length.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SaveTask {
+    /// The binary length searched.
+    pub bin_length: u32,
+    /// The states left, searched from the last one.
+    pub stack: Vec<State<Widest>>,
+}
+
+/// A single (decimal length, binary length) pair, searched by its own task.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cell {
+    /// The decimal length.
+    pub dec_length: u32,
+    /// The binary length.
+    pub bin_length: u32,
+}
+
+/// Everything needed to resume a search, which is what checkpoints store.
+#[derive(Serialize, Deserialize)]
+pub struct SaveState {
+    /// The decimal length being searched.
+    pub dec_length: u32,
+    /// The bases searched.
+    #[serde(default)]
+    pub bases: Bases,
+    /// The last decimal length to search, unbounded if None.
+    #[serde(default)]
+    pub max_dec_length: Option<u32>,
+    /// Only these binary lengths are searched, all of them if None.
+    #[serde(default)]
+    pub bin_lengths: Option<Vec<u32>>,
+    /// The pairs of lengths searched to the end.
+    #[serde(default)]
+    pub completed_cells: Vec<Cell>,
+    /// The states left to search in the current decimal length.
+    pub tasks: Vec<SaveTask>,
+    /// The palindromes found so far, in the order they were found.
+    #[serde(with = "uint::hex::vec")]
+    pub palindromes_found: Vec<Widest>,
+    /// Set if this is one of several checkpoints split from a single search.
+    #[serde(default)]
+    pub shard: Option<Shard>,
+}
+
+impl SaveState {
+    /// A search from `dec_length` up to `max_dec_length` which hasn't started yet.
+    pub fn new(
+        bases: Bases,
+        dec_length: u32,
+        max_dec_length: Option<u32>,
+        bin_lengths: Option<Vec<u32>>,
+    ) -> Self {
+        Self {
+            dec_length,
+            bases,
+            max_dec_length,
+            bin_lengths,
+            completed_cells: vec![],
+            tasks: vec![],
+            palindromes_found: vec![],
+            shard: None,
+        }
+    }
+
+    /// Checks that the bases are valid and that the first and last decimal lengths fit in the
+    /// widest supported integer. A search without a last decimal length stops with an error after
+    /// the last one that fits.
+    pub fn validate(&self) -> Result<(), String> {
+        self.bases.validate()?;
+        self.bases.check_fits(self.dec_length)?;
+        if let Some(max_dec_length) = self.max_dec_length {
+            self.bases.check_fits(max_dec_length)?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the binary length of a decimal length is wanted and not searched yet.
+    pub fn is_cell_wanted(&self, dec_length: u32, bin_length: u32) -> bool {
+        self.bin_lengths
+            .as_ref()
+            .is_none_or(|bin_lengths| bin_lengths.contains(&bin_length))
+            && !self.completed_cells.contains(&Cell {
+                dec_length,
+                bin_length,
+            })
+    }
+
+    /// The decimal lengths whose every binary length was searched to the end.
+    pub fn completed_lengths(&self) -> BTreeSet<u32> {
+        let dec_lengths: BTreeSet<u32> = self
+            .completed_cells
+            .iter()
+            .map(|cell| cell.dec_length)
+            .collect();
+        dec_lengths
+            .into_iter()
+            .filter(|&dec_length| {
+                if self.bases.check_fits(dec_length).is_err() {
+                    return false;
+                }
+                let layout = BaseLayout::<Widest>::new(self.bases, dec_length);
+                let (min_bin_length, max_bin_length) = bin_length_range(&layout, dec_length);
+                (min_bin_length..=max_bin_length).all(|bin_length| {
+                    self.completed_cells.contains(&Cell {
+                        dec_length,
+                        bin_length,
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// Marks a binary length of a decimal length searched to the end.
+    pub fn complete_cell(&mut self, dec_length: u32, bin_length: u32) {
+        let cell = Cell {
+            dec_length,
+            bin_length,
+        };
+        if !self.completed_cells.contains(&cell) {
+            self.completed_cells.push(cell);
+        }
+    }
+}
+
+/// How a search runs and reports what it finds.
+pub struct SearchConfig {
+    /// Where to save the search when it's interrupted or done.
+    pub checkpoint: Option<PathBuf>,
+    /// Seconds between checkpoints, None to only write one when the search stops.
+    pub checkpoint_interval: Option<Duration>,
+    /// Defaults to the format implied by the checkpoint's extension.
+    pub checkpoint_format: Option<CheckpointFormat>,
+    /// The number of previous checkpoints kept as backups.
+    pub backups: u32,
+    /// Prints table generation and other details as text, which JSON output always has.
+    pub verbose: bool,
+    /// In bytes, None to use all available memory.
+    pub memory_budget: Option<u64>,
+    /// Lookup tables cover at most this many digits, 0 for none, or as many as fit if None.
+    pub max_table_digits: Option<u32>,
+    /// Lookup tables are shrunk by at least this power of two, from 0 up to 6.
+    pub min_downscale_factor: u32,
+    /// Branches above this level are spawned as separate tasks.
+    pub spawn_depth: u32,
+    /// How events are printed when there is no `sink`.
+    pub format: OutputFormat,
+    /// Time between progress reports, None for none.
+    pub progress_interval: Option<Duration>,
+    /// Collects counts for every level and reports them when a decimal length is done.
+    pub stats: bool,
+    /// Buffers the palindromes of each decimal length and prints them in order once it's done.
+    pub sorted: bool,
+    /// Kept up to date with every palindrome found, in OEIS b-file format.
+    pub b_file: Option<PathBuf>,
+    /// Prints palindromes as they're found, work units report them with their results instead.
+    pub report: bool,
+    /// Receives the events instead of printing them in `format`.
+    pub sink: Option<Arc<dyn PalindromeSink>>,
+    /// Stops the search when set, which saves the states it has left in its save state. Safe to
+    /// set from a signal handler.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Stops the workers long enough to write a checkpoint when set, which the search does every
+    /// `checkpoint_interval` by itself.
+    pub checkpoint_due: Arc<AtomicBool>,
+    /// Held while writing the b-file, so concurrent finds don't write it over each other.
+    pub b_file_lock: Arc<Mutex<()>>,
+    /// The first error reporting an event, which stops the search, and any later one run with
+    /// this config.
+    pub report_error: Arc<OnceLock<String>>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            checkpoint: None,
+            checkpoint_interval: None,
+            checkpoint_format: None,
+            backups: 3,
+            verbose: false,
+            memory_budget: None,
+            max_table_digits: None,
+            min_downscale_factor: 0,
+            spawn_depth: 4,
+            format: OutputFormat::Text,
+            progress_interval: None,
+            stats: false,
+            sorted: false,
+            b_file: None,
+            report: true,
+            sink: None,
+            cancel: None,
+            checkpoint_due: Arc::default(),
+            b_file_lock: Arc::default(),
+            report_error: Arc::default(),
+        }
+    }
+}
+
+impl SearchConfig {
+    fn is_stopped(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+            || self.report_error.get().is_some()
+    }
+
+    /// The format to write the checkpoint at `path` in.
+    pub fn checkpoint_format(&self, path: &Path) -> CheckpointFormat {
+        self.checkpoint_format
+            .unwrap_or_else(|| CheckpointFormat::from_path(path))
+    }
+}
+
+/// Runs a search, writing checkpoints as configured and a last one when it stops.
+///
+/// Returns false if it was stopped before finishing its decimal lengths, e.g. by setting
+/// `SearchConfig::cancel` from a signal handler. Fails if the search or its b-file is invalid, if the last checkpoint can't
+/// be written, or if its events couldn't be reported.
+pub fn run_search(save_state: SaveState, config: &SearchConfig) -> Result<bool, String> {
+    run_search_state(save_state, config).map(|(completed, _)| completed)
+}
+
+// Also hands back the save state, to resume the search from.
+fn run_search_state(
+    save_state: SaveState,
+    config: &SearchConfig,
+) -> Result<(bool, SaveState), String> {
+    save_state
+        .validate()
+        .map_err(|error| format!("Invalid search: {error}"))?;
+    let start_time = Instant::now();
+    if let Some(b_file) = &config.b_file {
+        let mut notes = vec![];
+        bfile::check(b_file, &save_state, &mut notes)
+            .map_err(|error| format!("The b-file doesn't match the checkpoint: {error}"))?;
+        for message in notes {
+            events::report(Event::Warning { message }, config, start_time);
+        }
+        bfile::write(b_file, &save_state.palindromes_found)
+            .map_err(|error| format!("Error writing b-file {}: {error}", b_file.display()))?;
+    }
+
+    let save_state = Mutex::new(save_state);
+    let finished = AtomicBool::new(false);
+    let completed = std::thread::scope(|threads| {
+        let _finished = SetOnDrop(&finished);
+        if let (Some(_), Some(interval)) = (&config.checkpoint, config.checkpoint_interval) {
+            let finished = &finished;
+            threads.spawn(move || request_checkpoints(finished, interval, &config.checkpoint_due));
+        }
+        find_palindrome(&save_state, config, start_time)
+    });
+    if let Some(b_file) = &config.b_file {
+        write_b_file(b_file, config, start_time, &save_state);
+    }
+    if let Some(checkpoint) = &config.checkpoint {
+        let save_state = save_state.lock().unwrap();
+        checkpoint::write(
+            checkpoint,
+            &save_state,
+            config.checkpoint_format(checkpoint),
+            config.backups,
+        )
+        .map_err(|error| format!("Error writing checkpoint {}: {error}", checkpoint.display()))?;
+        events::report(
+            Event::CheckpointWritten {
+                path: checkpoint.clone(),
+                tasks: save_state.tasks.len(),
+            },
+            config,
+            start_time,
+        );
+    }
+
+    // A search whose events couldn't be reported stopped for that, but its checkpoint is written.
+    if let Some(error) = config.report_error.get() {
+        return Err(format!("Error reporting events: {error}"));
+    }
+    // A search which ran out of widths still keeps what it found up to then.
+    Ok((completed?, save_state.into_inner().unwrap()))
+}
+
+// Sets the flag when dropped, so threads waiting for it stop even if what they wait for panics.
+pub(crate) struct SetOnDrop<'a>(pub &'a AtomicBool);
+
+impl Drop for SetOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+// Asks the workers to stop for a checkpoint every `interval`, until `finished` is set.
+fn request_checkpoints(finished: &AtomicBool, interval: Duration, due: &AtomicBool) {
+    let mut last_request = Instant::now();
+    while !finished.load(Ordering::Relaxed) {
+        // Short naps so a finished search doesn't wait for a whole interval.
+        std::thread::sleep(interval.min(Duration::from_millis(100)));
+        if last_request.elapsed() >= interval {
+            due.store(true, Ordering::Relaxed);
+            last_request = Instant::now();
+        }
+    }
+}
+
+/// Stops a `Search` from another thread.
+#[derive(Clone)]
+pub struct Canceller(Arc<AtomicBool>);
+
+impl Canceller {
+    /// Stops the search, which still yields what it found before stopping.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A search running in the background, which yields its events as they happen.
+///
+/// Dropping it stops the search and waits for it.
+pub struct Search {
+    receiver: mpsc::Receiver<Event>,
+    canceller: Canceller,
+    handle: Option<JoinHandle<Result<(bool, SaveState), String>>>,
+}
+
+/// Starts searching from `save_state` in the background, e.g. the decimal lengths of
+/// `SaveState::new`, which are the search's bounds.
+///
+/// ```no_run
+/// use palindromes::{bases::Bases, search, SaveState, SearchConfig};
+///
+/// let save_state = SaveState::new(Bases::default(), 1, Some(30), None);
+/// for palindrome in search(save_state, SearchConfig::default()).palindromes() {
+///     println!("{palindrome}");
+/// }
+/// ```
+pub fn search(save_state: SaveState, config: SearchConfig) -> Search {
+    let (sender, receiver) = mpsc::channel();
+    let cancel = Arc::new(AtomicBool::new(false));
+    let config = SearchConfig {
+        sink: Some(Arc::new(ChannelSink::new(sender))),
+        cancel: Some(cancel.clone()),
+        ..config
+    };
+    let handle = std::thread::spawn(move || run_search_state(save_state, &config));
+
+    Search {
+        receiver,
+        canceller: Canceller(cancel),
+        handle: Some(handle),
+    }
+}
+
+impl Search {
+    /// Stops the search. What it found before stopping is still yielded.
+    pub fn cancel(&self) {
+        self.canceller.cancel();
+    }
+
+    /// Something to cancel the search with from elsewhere.
+    pub fn canceller(&self) -> Canceller {
+        self.canceller.clone()
+    }
+
+    /// Only the palindromes found.
+    pub fn palindromes(self) -> impl Iterator<Item = Widest> {
+        self.filter_map(|event| match event {
+            Event::Found { value, .. } => Some(value),
+            _ => None,
+        })
+    }
+
+    /// Waits for the search to end, and returns whether it searched all its decimal lengths and
+    /// the save state to resume it from, or why it couldn't run.
+    pub fn finish(mut self) -> Result<(bool, SaveState), String> {
+        self.handle.take().unwrap().join().unwrap()
+    }
+}
+
+impl Iterator for Search {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.cancel();
+            // A panic in the search was already printed.
+            let _ = handle.join();
+        }
+    }
+}
@@ -1,360 +1,306 @@
-#![allow(clippy::too_many_arguments)]
-use ethnum::u256;
-use rayon::Scope;
-use serde::{Deserialize, Serialize};
+use clap::Parser;
 use std::{
+    fmt::Display,
+    net::TcpListener,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
-        Mutex,
+        Arc,
     },
+    time::Duration,
     time::Instant,
 };
-use sysinfo::{MemoryRefreshKind, RefreshKind};
 
-mod par_bitmap_table;
+use palindromes::{
+    bases::Bases,
+    checkpoint::{self, CheckpointFormat},
+    cluster, inspect, run_search, shard, unit, verify, SaveState, SearchConfig,
+};
+
+mod cli;
 
-use par_bitmap_table::{LevelTable, LookupTable};
+use cli::{Cli, Command, RunArgs};
 
-const VERBOSE: bool = false;
+// The exit code of a command which failed, or of verify when it found problems.
+const EXIT_FAILED: i32 = 1;
+// The exit code of a search which stopped before finishing its range of decimal lengths.
+const EXIT_INTERRUPTED: i32 = 3;
 
-pub trait Bits {
-    fn bits(&self) -> u32;
+// Tells what failed along with why, to print instead of panicking.
+trait Context<T> {
+    fn context(self, what: &str) -> Result<T, String>;
 }
 
-impl Bits for u256 {
-    fn bits(&self) -> u32 {
-        Self::BITS - self.leading_zeros()
+impl<T, E: Display> Context<T> for Result<T, E> {
+    fn context(self, what: &str) -> Result<T, String> {
+        self.map_err(|error| format!("{what}: {error}"))
     }
 }
 
-impl Bits for u64 {
-    fn bits(&self) -> u32 {
-        Self::BITS - self.leading_zeros()
+fn search_config(run: &RunArgs, checkpoint: Option<PathBuf>) -> Result<SearchConfig, String> {
+    if let Some(threads) = run.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .context("Error setting up the thread pool")?;
     }
+
+    Ok(SearchConfig {
+        checkpoint,
+        checkpoint_format: run.checkpoint_format,
+        backups: run.backups,
+        checkpoint_interval: (run.checkpoint_interval > 0)
+            .then(|| Duration::from_secs(run.checkpoint_interval)),
+        verbose: run.verbose,
+        memory_budget: run.memory.map(|memory| memory << 20),
+        spawn_depth: run.spawn_depth,
+        format: run.format,
+        progress_interval: (run.progress > 0).then(|| Duration::from_secs(run.progress)),
+        sorted: run.sorted,
+        stats: run.stats,
+        ..SearchConfig::default()
+    })
 }
 
-impl Bits for usize {
-    fn bits(&self) -> u32 {
-        Self::BITS - self.leading_zeros()
+// Loads a checkpoint, telling what it took, e.g. upgrading it or falling back to a backup.
+fn load_checkpoint(path: &Path) -> Result<SaveState, String> {
+    let loaded = checkpoint::load(path)
+        .map_err(|error| format!("Error loading checkpoint {}: {error}", path.display()))?;
+    for note in &loaded.notes {
+        eprintln!("{note}");
     }
-}
 
-#[derive(Serialize, Deserialize)]
-struct State {
-    current_num: u256,
-    bin_num: u256,
-    is_odd: Option<bool>,
-    level: u32,
+    Ok(loaded.save_state)
 }
 
-fn find_palindrome_recursive<'scope>(
-    mut stack: Vec<State>,
-    dec_length: u32,
-    bin_length: u32,
-    digit_cache: &'scope [[u256; 10]],
-    max_dec_cache: &'scope [u256],
-    max_bin_cache: &'scope [u256],
-    lookup_table: &'scope LookupTable,
-    start_time: Instant,
-    scope: &Scope<'scope>,
-    save_state: &'scope Mutex<SaveState>,
-) {
-    loop {
-        if TERMINATE.load(Ordering::Relaxed) {
-            save_state
-                .lock()
-                .unwrap()
-                .tasks
-                .push(SaveTask { bin_length, stack });
-            return;
-        }
-        let Some(state) = stack.pop() else { return };
-
-        let current_num = state.current_num;
-        let bin_num = state.bin_num;
-        let level = state.level;
+fn write_checkpoint(
+    path: &Path,
+    save_state: &SaveState,
+    format: CheckpointFormat,
+) -> Result<(), String> {
+    checkpoint::write(path, save_state, format, 0)
+        .map_err(|error| format!("Error writing checkpoint {}: {error}", path.display()))
+}
 
-        let digits = match state.is_odd {
-            Some(true) => (1..=9).step_by(2),
-            Some(false) => (0..=8).step_by(2),
-            None => (0..=9).step_by(1),
-        };
+// Runs a search which can be interrupted to save a checkpoint, and exits if it was.
+fn run_interruptible(save_state: SaveState, config: SearchConfig) -> Result<(), String> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    if config.checkpoint.is_some() {
+        let cancel = cancel.clone();
+        ctrlc::set_handler(move || cancel.store(true, Ordering::Relaxed))
+            .context("Error setting Ctrl-C handler")?;
+    }
+    let config = SearchConfig {
+        cancel: Some(cancel),
+        ..config
+    };
+    if !run_search(save_state, &config).context("Error running search")? {
+        std::process::exit(EXIT_INTERRUPTED);
+    }
 
-        if (state.level + 1) * 2 >= dec_length {
-            for digit in digits {
-                let new_num = state.current_num + digit_cache[level as usize][digit as usize];
-                let leading_zeros = new_num.leading_zeros();
-                if leading_zeros + bin_length != u256::BITS {
-                    continue;
-                }
+    Ok(())
+}
 
-                let reversed = new_num.reverse_bits() >> leading_zeros;
-                if reversed == new_num {
-                    println!("{:.4}: {}", start_time.elapsed().as_secs_f32(), new_num);
-                    save_state.lock().unwrap().palindromes_found.push(new_num);
-                }
-            }
+fn main() {
+    if let Err(error) = run(Cli::parse().command) {
+        eprintln!("{error}");
+        std::process::exit(EXIT_FAILED);
+    }
+}
 
-            continue;
+fn run(command: Command) -> Result<(), String> {
+    match command {
+        Command::Search(args) => {
+            let bases = Bases {
+                outer: args.outer,
+                inner: args.inner,
+            };
+            bases.validate().context("Invalid bases")?;
+            let (from, to) = match args.length {
+                Some(length) => (length, Some(length)),
+                None => (args.from, args.to),
+            };
+            let save_state = SaveState::new(bases, from.max(1), to, args.bin_lengths);
+            let config = SearchConfig {
+                b_file: args.b_file,
+                ..search_config(&args.run, args.checkpoint)?
+            };
+            run_interruptible(save_state, config)?;
         }
-
-        let max_bin_add = max_bin_cache[level as usize];
-        let max_dec_add = max_dec_cache[level as usize];
-
-        for digit in digits {
-            let new_num = current_num + digit_cache[level as usize][digit as usize];
-            let new_bin_num = bin_num + (((new_num >> level) & 1) << (bin_length - level - 1));
-
-            let new_max_dec = new_num + max_dec_add;
-
-            if new_bin_num + max_bin_add < new_num || new_max_dec < new_bin_num {
-                continue;
-            }
-
-            let msb_set_bits = (bin_length as i32) - ((new_max_dec ^ new_num).bits() as i32);
-
-            if !lookup_table.lookup(new_num, msb_set_bits, level + 1, bin_length) {
-                continue;
+        Command::Resume(args) => {
+            let mut save_state = load_checkpoint(&args.checkpoint)?;
+            if args.to.is_some() {
+                save_state.max_dec_length = args.to;
             }
-
-            let is_odd = if msb_set_bits <= (level as i32 + 1) {
-                None
+            let config = SearchConfig {
+                b_file: args.b_file,
+                ..search_config(&args.run, Some(args.checkpoint))?
+            };
+            run_interruptible(save_state, config)?;
+        }
+        Command::Verify(args) => {
+            let (entries, bases, mut complete) = if args.results {
+                let results = verify::read_results(&args.file).context("Error reading results")?;
+                let bases = Bases {
+                    outer: args.outer,
+                    inner: args.inner,
+                };
+                bases.validate().context("Invalid bases")?;
+                (results.entries, bases, results.finished)
             } else {
-                let wanted_digit = *(new_max_dec >> (bin_length - level - 2)).low() as u64 & 1;
-                Some(*(new_num >> (level + 1)).low() as u64 & 1 != wanted_digit)
+                let save_state = load_checkpoint(&args.file)?;
+                let entries = save_state
+                    .palindromes_found
+                    .iter()
+                    .enumerate()
+                    .map(|(index, palindrome)| verify::Entry {
+                        line: index + 1,
+                        value: palindrome.to_string(),
+                    })
+                    .collect();
+                (entries, save_state.bases, save_state.completed_lengths())
             };
+            complete.extend(1..=args.complete_to.unwrap_or(0));
 
-            if level < 4 {
-                scope.spawn(move |scope| {
-                    find_palindrome_recursive(
-                        vec![State {
-                            current_num: new_num,
-                            bin_num: new_bin_num,
-                            is_odd,
-                            level: level + 1,
-                        }],
-                        dec_length,
-                        bin_length,
-                        digit_cache,
-                        max_dec_cache,
-                        max_bin_cache,
-                        lookup_table,
-                        start_time,
-                        scope,
-                        save_state,
+            let problems = verify::verify(&entries, bases, &complete, args.enumerate_to);
+            for problem in &problems {
+                println!("{problem}");
+            }
+            if !problems.is_empty() {
+                std::process::exit(EXIT_FAILED);
+            }
+            println!("All {} palindromes are valid", entries.len());
+            if let Some(&last) = complete.last() {
+                if last > args.enumerate_to {
+                    println!(
+                        "Decimal lengths above {} weren't checked for missing palindromes",
+                        args.enumerate_to
                     );
-                })
-            } else {
-                stack.push(State {
-                    current_num: new_num,
-                    bin_num: new_bin_num,
-                    is_odd,
-                    level: level + 1,
-                });
+                }
             }
         }
-    }
-}
-
-fn find_palindrome(save_state: &Mutex<SaveState>, start_time: Instant) {
-    loop {
-        let dec_length = save_state.lock().unwrap().dec_length;
-        let max_bin_length = (u256::from(10u32).pow(dec_length) - 1).bits();
-        let min_bin_length = if dec_length == 1 {
-            1
-        } else {
-            (u256::from(10u32).pow(dec_length - 1) + 1).bits()
-        };
-        let digit_cache = get_digit_cache(dec_length);
-        let max_dec_cache = get_max_cache(dec_length, 10);
-        let mut lookup_table = LookupTable::new(&digit_cache);
-
-        if VERBOSE {
-            println!(
-                "{:.4}: Starting decimal length: {}",
-                start_time.elapsed().as_secs_f32(),
-                dec_length
-            );
+        Command::ConvertCheckpoint {
+            input,
+            output,
+            format,
+        } => {
+            let save_state = load_checkpoint(&input)?;
+            let format = format.unwrap_or_else(|| CheckpointFormat::from_path(&output));
+            write_checkpoint(&output, &save_state, format)?;
         }
-
-        let max_bin_caches: Vec<_> = (min_bin_length..=max_bin_length)
-            .map(|bin_length| get_max_cache(bin_length, 2))
-            .collect();
-
-        let mut remaining_memory = sysinfo::System::new_with_specifics(
-            RefreshKind::nothing().with_memory(MemoryRefreshKind::nothing().with_ram()),
-        )
-        .available_memory();
-        // println!("available memory: {:?}", remaining_memory);
-        let desired_max_cache_digits =
-            (dec_length as f64 * 5f64.log2() / (2f64 * 5f64.log2() + 1f64) / 2f64).floor() as u32;
-        let max_cache_digits = (remaining_memory * 8)
-            .ilog10()
-            .min(desired_max_cache_digits);
-        // let max_cache_digits = 11; //(available_memory / std::mem::size_of::<u64>() as u64).ilog10();
-        if VERBOSE {
-            println!("max_cache_digits: {desired_max_cache_digits}");
+        Command::SplitCheckpoint { checkpoint, shards } => {
+            let save_state = load_checkpoint(&checkpoint)?;
+            let format = CheckpointFormat::from_path(&checkpoint);
+            let shards = shard::split(save_state, shards).context("Error splitting checkpoint")?;
+            for (index, shard) in shards.iter().enumerate() {
+                let mut name = checkpoint.file_stem().unwrap_or_default().to_os_string();
+                name.push(format!(".shard{index}"));
+                if let Some(extension) = checkpoint.extension() {
+                    name.push(".");
+                    name.push(extension);
+                }
+                let path = checkpoint.with_file_name(name);
+                write_checkpoint(&path, shard, format)?;
+                println!("{}", path.display());
+            }
+        }
+        Command::MergeCheckpoints { output, shards } => {
+            let shards = shards
+                .iter()
+                .map(|path| load_checkpoint(path))
+                .collect::<Result<_, _>>()?;
+            let save_state = shard::merge(shards).context("Error merging checkpoints")?;
+            let format = CheckpointFormat::from_path(&output);
+            write_checkpoint(&output, &save_state, format)?;
         }
-        for num_digits in (2..=max_cache_digits).rev() {
-            let Some((downscale_factor, size)) =
-                LevelTable::calculate_memory_requirements(num_digits, remaining_memory)
-            else {
-                continue;
+        Command::ExportUnits(args) => {
+            let bases = Bases {
+                outer: args.outer,
+                inner: args.inner,
             };
-            remaining_memory -= size;
-            if VERBOSE {
-                println!(
-                    "Generating table for decimal length {}, num_digits: {}",
-                    dec_length, num_digits
-                );
+            let units = unit::export(
+                bases,
+                args.length,
+                args.bin_lengths.as_deref(),
+                args.prefix_digits,
+                args.units,
+            )
+            .context("Error exporting work units")?;
+            for unit in units {
+                println!("{}", serde_json::to_string(&unit).unwrap());
             }
-            if lookup_table.generate(num_digits, downscale_factor, &digit_cache) {
-                let level = digit_cache.len() - num_digits as usize;
-                let instance = lookup_table.sub_caches[level].as_ref().unwrap();
-                if VERBOSE {
-                    println!(
-                        "{:.4}: Generated table for decimal length {}, num_digits: {}, size: {}, factor: {}",
-                        start_time.elapsed().as_secs_f32(),
-                        dec_length,
-                        num_digits,
-                        instance.size(),
-                        10u64.pow(num_digits) as f64 / (instance.size() * 8) as f64
-                    );
+        }
+        Command::RunUnits { units, run } => {
+            let units = unit::read(&units).context("Error reading work units")?;
+            let config = SearchConfig {
+                report: false,
+                ..search_config(&run, None)?
+            };
+            unit::run(&units, &config, |result| {
+                println!("{}", serde_json::to_string(&result).unwrap());
+            })
+            .context("Error running work units")?;
+        }
+        Command::Coordinate {
+            units,
+            listen,
+            results,
+            format,
+            verbose,
+        } => {
+            let units = unit::read(&units).context("Error reading work units")?;
+            let listener = TcpListener::bind(&listen).context("Error listening for workers")?;
+            let config = SearchConfig {
+                format,
+                verbose,
+                ..SearchConfig::default()
+            };
+            cluster::coordinate(units, listener, results.as_deref(), &config)
+                .context("Error coordinating workers")?;
+        }
+        Command::Work { connect, run } => {
+            // An idle worker has nothing to hand back, so it can just go.
+            let cancel = Arc::new(AtomicBool::new(false));
+            let handler_cancel = cancel.clone();
+            ctrlc::set_handler(move || {
+                if cluster::is_searching() {
+                    handler_cancel.store(true, Ordering::Relaxed);
+                } else {
+                    std::process::exit(EXIT_INTERRUPTED);
                 }
+            })
+            .context("Error setting Ctrl-C handler")?;
+            let config = SearchConfig {
+                report: false,
+                cancel: Some(cancel),
+                ..search_config(&run, None)?
+            };
+            let completed =
+                cluster::work(&connect, &config).context("Error working for coordinator")?;
+            if !completed {
+                std::process::exit(EXIT_INTERRUPTED);
             }
         }
-
-        rayon::scope(|scope| {
-            let existing_tasks = &mut save_state.lock().unwrap().tasks;
-            let tasks: Vec<SaveTask> = if existing_tasks.is_empty() {
-                (min_bin_length..=max_bin_length)
-                    .map(|bin_length| SaveTask {
-                        stack: vec![State {
-                            current_num: u256::ZERO,
-                            bin_num: u256::ZERO,
-                            is_odd: Some(true),
-                            level: 0,
-                        }],
-                        bin_length,
-                    })
-                    .collect()
-            } else {
-                std::mem::take(existing_tasks)
+        Command::Inspect { checkpoint } => {
+            println!("{}", inspect::inspect(&load_checkpoint(&checkpoint)?));
+        }
+        Command::Bench(args) => {
+            let bases = Bases {
+                outer: args.outer,
+                inner: args.inner,
             };
-
-            for task in tasks {
-                let bin_length = task.bin_length;
-                let digit_cache_ref = &digit_cache;
-                let max_dec_cache_ref = &max_dec_cache;
-                let lookup_table_ref = &lookup_table;
-                let max_bin_cache_ref = &max_bin_caches[(bin_length - min_bin_length) as usize];
-                scope.spawn(move |scope| {
-                    find_palindrome_recursive(
-                        task.stack,
-                        dec_length,
-                        bin_length,
-                        digit_cache_ref,
-                        max_dec_cache_ref,
-                        max_bin_cache_ref,
-                        lookup_table_ref,
-                        start_time,
-                        scope,
-                        save_state,
-                    );
-                });
-            }
-        });
-        if VERBOSE {
+            bases.validate().context("Invalid bases")?;
+            let save_state = SaveState::new(bases, 1, Some(args.to), None);
+            let start_time = Instant::now();
+            run_search(save_state, &search_config(&args.run, None)?)
+                .context("Error running search")?;
             println!(
-                "{:.4}: Finished decimal length {}",
+                "{:.4}: Searched decimal lengths up to {}",
                 start_time.elapsed().as_secs_f32(),
-                dec_length
+                args.to
             );
         }
-
-        if save_state.lock().unwrap().tasks.is_empty() {
-            save_state.lock().unwrap().dec_length += 1;
-        } else {
-            return;
-        }
     }
-}
-
-fn get_max_cache(length: u32, base: u32) -> Vec<u256> {
-    let cache_length = (length + 1) / 2;
-    (1..cache_length)
-        .map(|i| u256::from(base).pow(length - i) - u256::from(base).pow(i))
-        .collect()
-}
-
-fn get_digit_cache(dec_length: u32) -> Vec<[u256; 10]> {
-    let cache_length = (dec_length + 1) / 2;
-    (0..cache_length)
-        .map(|i| {
-            let j = dec_length - i - 1;
-            let mut entry = u256::from(10u32).pow(i);
-            if i != j {
-                entry += u256::from(10u32).pow(j);
-            }
-
-            std::array::from_fn(|i| entry * i as u128)
-        })
-        .collect()
-}
-
-static TERMINATE: AtomicBool = AtomicBool::new(false);
-
-#[derive(Serialize, Deserialize)]
-struct SaveTask {
-    bin_length: u32,
-    stack: Vec<State>,
-}
-
-#[derive(Serialize, Deserialize)]
-struct SaveState {
-    dec_length: u32,
-    tasks: Vec<SaveTask>,
-    palindromes_found: Vec<u256>,
-}
-
-fn main() {
-    let save_path_arg = std::env::args().nth(1);
-    let mut save_state = Mutex::new(SaveState {
-        dec_length: 1,
-        tasks: vec![],
-        palindromes_found: vec![],
-    });
-    if let Some(save_path) = &save_path_arg {
-        let load_result = std::fs::read_to_string(save_path);
-        if let Ok(contents) = load_result {
-            *save_state.get_mut().unwrap() = serde_json::from_str(&contents).unwrap()
-        }
-
-        ctrlc::set_handler(move || TERMINATE.store(true, Ordering::Relaxed))
-            .expect("Error setting Ctrl-C handler");
-    }
-    let start_time = Instant::now();
-    find_palindrome(&save_state, start_time);
-    if let Some(save_path) = &save_path_arg {
-        let serialized_save_state = serde_json::to_string(&*save_state.lock().unwrap()).unwrap();
-        std::fs::write(save_path, serialized_save_state).unwrap();
-    }
-
-    // table_tests();
-}
 
-#[allow(dead_code)]
-fn table_tests() {
-    let digit_cache = get_digit_cache(46);
-    let mut lookup = par_bitmap_table::LookupTable::new(&digit_cache);
-    let start_time = Instant::now();
-    let num_digits = 10;
-    lookup.generate(num_digits, 3, &digit_cache);
-    let level = digit_cache.len() - num_digits as usize;
-    println!("{:.4}: Finished bitmap", start_time.elapsed().as_secs_f32());
-    let sat = lookup.sub_caches[level].as_ref().unwrap().saturation();
-    println!(
-        "{:.4}: Bitmap saturation: {sat}",
-        start_time.elapsed().as_secs_f32()
-    );
+    Ok(())
 }
@@ -1,4 +1,7 @@
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
 
 use ethnum::u256;
 use rayon::{
@@ -6,20 +9,26 @@ use rayon::{
     prelude::*,
 };
 
-use crate::Bits as _;
+use crate::{
+    bases::{BaseLayout, Bases},
+    get_digit_cache,
+    uint::UInt,
+    Bits as _,
+};
 
 struct DigitRange<'a> {
     start: u64,
     end: u64,
-    digit_cache_64: &'a [[u64; 10]],
+    digit_cache_64: &'a [Vec<u64>],
 }
 
 impl<'a> DigitRange<'a> {
-    fn new(digit_cache_64: &'a [[u64; 10]]) -> Self {
+    fn new(digit_cache_64: &'a [Vec<u64>]) -> Self {
+        let base = digit_cache_64[0].len() as u64;
         Self {
             digit_cache_64,
             start: 0,
-            end: 10u64.pow(digit_cache_64.len() as u32) - 1,
+            end: base.pow(digit_cache_64.len() as u32) - 1,
         }
     }
 }
@@ -48,13 +57,14 @@ impl UnindexedProducer for DigitRange<'_> {
     where
         F: rayon::iter::plumbing::Folder<Self::Item>,
     {
+        let base = self.digit_cache_64[0].len() as u64;
         let mut state = vec![(0usize, 0u64); self.digit_cache_64.len() + 1];
         let mut end = vec![0; self.digit_cache_64.len()];
         for i in 0..end.len() {
-            let start_digit = self.start % 10;
-            let end_digit = self.end % 10;
-            self.start /= 10;
-            self.end /= 10;
+            let start_digit = self.start % base;
+            let end_digit = self.end % base;
+            self.start /= base;
+            self.end /= base;
             state[i].0 = start_digit as usize;
             end[i] = end_digit as usize;
         }
@@ -74,7 +84,7 @@ impl UnindexedProducer for DigitRange<'_> {
 }
 
 struct DigitIterator<'a> {
-    digit_cache_64: &'a [[u64; 10]],
+    digit_cache_64: &'a [Vec<u64>],
     state: Option<Vec<(usize, u64)>>,
     end: Vec<usize>,
 }
@@ -88,6 +98,7 @@ impl std::iter::Iterator for DigitIterator<'_> {
         let sum = stack[1].1.wrapping_add(self.digit_cache_64[0][stack[0].0]);
         let ret = sum.reverse_bits();
 
+        let max_digit = self.digit_cache_64[0].len() - 1;
         let mut plus_idx = 0;
         loop {
             if stack.iter().zip(self.end.iter()).all(|((i, _), j)| i == j) {
@@ -95,7 +106,7 @@ impl std::iter::Iterator for DigitIterator<'_> {
                 return Some(ret);
             }
 
-            if stack[plus_idx].0 < 9 {
+            if stack[plus_idx].0 < max_digit {
                 stack[plus_idx].0 += 1;
                 break;
             }
@@ -126,15 +137,36 @@ impl ParallelIterator for DigitRange<'_> {
     }
 }
 
+/// The values of the outer digits from `level` on, shifted right by `shift` and cut to 64 bits.
 #[inline(never)]
-pub fn get_digit_cache_64(digit_cache: &[[u256; 10]], level: usize) -> Vec<[u64; 10]> {
+pub fn get_digit_cache_64<T: UInt>(
+    digit_cache: &[Vec<T>],
+    level: usize,
+    shift: u32,
+) -> Vec<Vec<u64>> {
     digit_cache
         .iter()
         .skip(level)
-        .map(|c| c.map(|n| *(n >> level).low() as u64))
+        .map(|c| c.iter().map(|&n| (n >> shift).low_u64()).collect())
         .collect()
 }
 
+// Reverses the order of the digits in `num`, keeping the order of bits inside each digit.
+fn reverse_digits(num: u64, digit_bits: u32) -> u64 {
+    let reversed = num.reverse_bits();
+    if digit_bits == 1 {
+        return reversed;
+    }
+
+    let mask = u64::MAX >> (u64::BITS - digit_bits);
+    (0..u64::BITS / digit_bits).fold(0, |acc, i| {
+        let digit = (reversed >> (i * digit_bits)) & mask;
+        acc | (digit.reverse_bits() >> (u64::BITS - digit_bits)) << (i * digit_bits)
+    })
+}
+
+/// The possible low inner digits of the numbers below one level of the search tree, as a bitmap
+/// which can be shrunk to save memory at the cost of pruning less.
 #[derive(Clone, Debug)]
 pub struct LevelTable {
     bitmap: Vec<u64>,
@@ -160,19 +192,23 @@ impl LevelTable {
         self.bitmap[entry as usize / 64] & shifted_mask != 0
     }
 
-    fn populate(&mut self, level: usize, digit_cache: &[[u256; 10]]) {
-        let digit_cache_64 = get_digit_cache_64(digit_cache, level);
+    fn populate<T: UInt>(&mut self, level: usize, shift: u32, digit_cache: &[Vec<T>]) {
+        let digit_cache_64 = get_digit_cache_64(digit_cache, level, shift);
         DigitRange::new(&digit_cache_64).for_each(|n| {
             self.insert(n);
         });
     }
 
+    /// The table size for `num_digits` digits which fits in `available_memory`, with the smallest
+    /// downscale factor from `min_downscale_factor` on. Returns the factor and the size in bytes.
     pub fn calculate_memory_requirements(
         num_digits: u32,
+        base: u32,
         available_memory: u64,
+        min_downscale_factor: u32,
     ) -> Option<(u32, u64)> {
-        let sub_cache_size = 10u64.pow(num_digits);
-        for downscale_factor in 0..=6 {
+        let sub_cache_size = (base as u64).pow(num_digits);
+        for downscale_factor in min_downscale_factor..=6 {
             let length =
                 sub_cache_size.next_power_of_two() as usize / 1usize.wrapping_shl(downscale_factor);
             let size = (length * size_of::<u64>()) as u64;
@@ -184,11 +220,12 @@ impl LevelTable {
         None
     }
 
+    /// The size of the bitmap in bytes.
     pub fn size(&self) -> usize {
         self.bitmap.len() * size_of::<u64>()
     }
 
-    #[allow(dead_code)]
+    /// The fraction of bits set, the lower the more the table prunes.
     pub fn saturation(&self) -> f64 {
         let mut count = 0u64;
         for &word in &self.bitmap {
@@ -198,8 +235,13 @@ impl LevelTable {
         count as f64 / (self.bitmap.len() * u64::BITS as usize) as f64
     }
 
-    fn new(num_digits: u32, downscale_factor: u32, digit_cache: &[[u256; 10]]) -> Option<Self> {
-        let sub_cache_size = 10u64.pow(num_digits);
+    fn new<T: UInt>(
+        num_digits: u32,
+        downscale_factor: u32,
+        shift: u32,
+        digit_cache: &[Vec<T>],
+    ) -> Option<Self> {
+        let sub_cache_size = (digit_cache[0].len() as u64).pow(num_digits);
         if sub_cache_size < 64 {
             return None;
         }
@@ -219,81 +261,138 @@ impl LevelTable {
 
         let level = digit_cache.len() as u32 - num_digits;
 
-        instance.populate(level as usize, digit_cache);
+        instance.populate(level as usize, shift, digit_cache);
 
         Some(instance)
     }
 
-    fn lookup(&self, current_num: u256, level: u32, known_bits: u32, bin_length: u32) -> bool {
+    fn lookup<T: UInt>(
+        &self,
+        current_num: T,
+        level: u32,
+        known_bits: u32,
+        bin_length: u32,
+        digit_bits: u32,
+    ) -> bool {
         if known_bits < self.min_lookup_bits {
             return true;
         }
 
-        let current_bits = *(current_num >> level).low() as u64;
+        let current_bits = (current_num >> level).low_u64();
         let shift = bin_length as i32 - level as i32 - 64;
-        let final_bits = (if shift > 0 {
-            *(current_num >> shift).low() as u64
-        } else {
-            (*current_num.low() as u64) << -shift
-        })
-        .reverse_bits();
+        let final_bits = reverse_digits(
+            if shift > 0 {
+                (current_num >> shift as u32).low_u64()
+            } else {
+                current_num.low_u64() << -shift
+            },
+            digit_bits,
+        );
 
         let lookup_bits = final_bits.wrapping_sub(current_bits).reverse_bits();
         self.contains(lookup_bits, known_bits)
     }
 }
 
+/// The lookup tables of every level of a decimal length, most of which have none.
 #[derive(Clone, Debug)]
 pub struct LookupTable {
-    // index is the recursion level.
+    /// index is the recursion level.
     pub sub_caches: Vec<Option<LevelTable>>,
+    // index is the recursion level, the number of lowest bits fixed by the known outer digits.
+    shifts: Vec<u32>,
+    digit_bits: u32,
 }
 
 impl LookupTable {
-    pub fn new(digit_cache: &[[u256; 10]]) -> Self {
+    /// Empty tables for the levels of `digit_cache`, generated one by one with `generate`.
+    pub fn new<T: UInt>(digit_cache: &[Vec<T>], layout: &BaseLayout<T>) -> Self {
+        let digit_bits = layout.digit_bits.unwrap_or(1);
         Self {
             sub_caches: vec![None; digit_cache.len()],
+            shifts: layout.fixed_digits[..digit_cache.len()]
+                .iter()
+                .map(|fixed_digits| fixed_digits * digit_bits)
+                .collect(),
+            digit_bits,
         }
     }
 
-    pub fn generate(
+    /// Tables rely on reversing 64 bit words, so they need binary digits which fit in them evenly.
+    pub fn is_supported<T: UInt>(layout: &BaseLayout<T>) -> bool {
+        layout
+            .digit_bits
+            .is_some_and(|digit_bits| u64::BITS % digit_bits == 0)
+    }
+
+    /// Generates the table covering the last `num_digits` levels, shrunk by 2^`downscale_factor`.
+    /// Returns false if it doesn't apply to this decimal length.
+    pub fn generate<T: UInt>(
         &mut self,
         num_digits: u32,
         downscale_factor: u32,
-        digit_cache: &[[u256; 10]],
+        digit_cache: &[Vec<T>],
     ) -> bool {
         if num_digits as usize > digit_cache.len() {
             return false;
         }
 
         let level = digit_cache.len() - num_digits as usize;
-        if let Some(level_table) = LevelTable::new(num_digits, downscale_factor, digit_cache) {
-            self.sub_caches[level] = Some(level_table);
-        }
+        let Some(level_table) = LevelTable::new(
+            num_digits,
+            downscale_factor,
+            self.shifts[level],
+            digit_cache,
+        ) else {
+            return false;
+        };
+        self.sub_caches[level] = Some(level_table);
 
         true
     }
 
-    pub fn lookup(
+    /// Whether a number at `level` can still be a palindrome `bin_length` bits long, given that its
+    /// `msb_set_bits` highest bits are already known. Always true without a table at the level.
+    pub fn lookup<T: UInt>(
         &self,
-        current_num: u256,
+        current_num: T,
         msb_set_bits: i32,
         level: u32,
         bin_length: u32,
     ) -> bool {
-        if (level as i32) > msb_set_bits {
+        let shift = self.shifts[level as usize];
+        if (shift as i32) > msb_set_bits {
             return true;
         }
 
         self.sub_caches[level as usize]
             .as_ref()
-            .map_or(true, |level_table| {
+            .is_none_or(|level_table| {
                 level_table.lookup(
                     current_num,
-                    level,
-                    (msb_set_bits as u32) - level,
+                    shift,
+                    (msb_set_bits as u32) - shift,
                     bin_length,
+                    self.digit_bits,
                 )
             })
     }
 }
+
+// Times generating and measures the saturation of a single table, for trying out changes to them.
+#[allow(dead_code)]
+fn table_tests() {
+    let digit_cache = get_digit_cache::<u256>(46, 10);
+    let layout = BaseLayout::new(Bases::default(), 46);
+    let mut lookup = LookupTable::new(&digit_cache, &layout);
+    let start_time = Instant::now();
+    let num_digits = 10;
+    lookup.generate(num_digits, 3, &digit_cache);
+    let level = digit_cache.len() - num_digits as usize;
+    println!("{:.4}: Finished bitmap", start_time.elapsed().as_secs_f32());
+    let sat = lookup.sub_caches[level].as_ref().unwrap().saturation();
+    println!(
+        "{:.4}: Bitmap saturation: {sat}",
+        start_time.elapsed().as_secs_f32()
+    );
+}
@@ -0,0 +1,289 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    events::{self, Event},
+    SearchConfig,
+};
+
+/// What happened to the candidates at one level of the search tree.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LevelCounts {
+    /// States expanded.
+    pub visited: u64,
+    /// Cut because the binary and decimal numbers can't meet anymore.
+    pub cut_bound: u64,
+    /// Cut by the level's `LevelTable`.
+    pub cut_table: u64,
+    /// Cut by the remainder tables.
+    pub cut_remainder: u64,
+    /// Complete numbers checked for being a palindrome in the inner base.
+    pub leaves_checked: u64,
+}
+
+/// Counters shared by every task searching one decimal length.
+#[derive(Default)]
+pub struct Progress {
+    /// States expanded.
+    pub nodes: AtomicU64,
+    /// Branches cut because the binary and decimal numbers can't meet anymore.
+    pub pruned_interval: AtomicU64,
+    /// Branches cut by the lookup tables.
+    pub pruned_table: AtomicU64,
+    // Nodes expanded at every level since the last report.
+    levels: Vec<AtomicU64>,
+    // The share of the binary lengths' search trees finished, where a whole tree is 1.
+    done: Mutex<f64>,
+    // Everything counted at every level, only collected when asked for.
+    stats: Option<Mutex<Vec<LevelCounts>>>,
+}
+
+/// What a single task counted since it last added its counts to the shared ones, kept apart so
+/// the search doesn't touch atomics or locks for every node.
+pub(crate) struct Counts {
+    expanded: u64,
+    pub levels: Vec<LevelCounts>,
+    /// The share of the search tree finished, by reaching the leaves or pruning.
+    pub done: f64,
+}
+
+impl Counts {
+    // Adding up is cheap enough at this rate and keeps the progress reasonably live.
+    const FLUSH_INTERVAL: u64 = 1 << 16;
+
+    pub fn new(num_levels: usize) -> Self {
+        Self {
+            expanded: 0,
+            levels: vec![LevelCounts::default(); num_levels],
+            done: 0.0,
+        }
+    }
+
+    pub fn expand(&mut self, level: u32, progress: &Progress) {
+        self.expanded += 1;
+        self.levels[level as usize].visited += 1;
+        if self.expanded == Self::FLUSH_INTERVAL {
+            self.flush(progress);
+        }
+    }
+
+    pub fn flush(&mut self, progress: &Progress) {
+        progress.nodes.fetch_add(self.expanded, Ordering::Relaxed);
+        progress.pruned_interval.fetch_add(
+            self.levels.iter().map(|counts| counts.cut_bound).sum(),
+            Ordering::Relaxed,
+        );
+        progress.pruned_table.fetch_add(
+            self.levels.iter().map(|counts| counts.cut_table).sum(),
+            Ordering::Relaxed,
+        );
+        for (total, counts) in progress.levels.iter().zip(&self.levels) {
+            total.fetch_add(counts.visited, Ordering::Relaxed);
+        }
+        if let Some(stats) = &progress.stats {
+            for (total, counts) in stats.lock().unwrap().iter_mut().zip(&self.levels) {
+                total.visited += counts.visited;
+                total.cut_bound += counts.cut_bound;
+                total.cut_table += counts.cut_table;
+                total.cut_remainder += counts.cut_remainder;
+                total.leaves_checked += counts.leaves_checked;
+            }
+        }
+        progress.finish(self.done);
+        self.expanded = 0;
+        self.levels.fill(LevelCounts::default());
+        self.done = 0.0;
+    }
+}
+
+impl Progress {
+    /// Counters for a decimal length with `num_levels` levels, which also collects every level's
+    /// counts if `stats` is set.
+    pub fn new(num_levels: usize, stats: bool) -> Self {
+        Self {
+            levels: (0..num_levels).map(|_| AtomicU64::new(0)).collect(),
+            stats: stats.then(|| Mutex::new(vec![LevelCounts::default(); num_levels])),
+            ..Self::default()
+        }
+    }
+
+    /// The counts of every level, if they were collected.
+    pub fn stats(&self) -> Option<Vec<LevelCounts>> {
+        self.stats
+            .as_ref()
+            .map(|stats| stats.lock().unwrap().clone())
+    }
+
+    /// Records that a share of a binary length's search tree is finished.
+    pub fn finish(&self, share: f64) {
+        *self.done.lock().unwrap() += share;
+    }
+
+    /// Sets the finished share, e.g. to what's left of the tasks after a checkpoint.
+    pub fn set_done(&self, done: f64) {
+        *self.done.lock().unwrap() = done;
+    }
+
+    /// The share of the binary lengths' search trees finished, where a whole tree is 1.
+    pub fn done(&self) -> f64 {
+        *self.done.lock().unwrap()
+    }
+}
+
+/// Estimates when a decimal length is done from the rate its shares were finished at recently.
+///
+/// Branches pruned near the root finish large shares right away while the rest takes far longer,
+/// so the rate since the start of the length would promise it done much too soon.
+pub(crate) struct Eta {
+    // When each report was made and how much was done by then, going back one window.
+    samples: VecDeque<(Duration, f64)>,
+}
+
+impl Eta {
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Records that `done` of `total` was finished `elapsed` into the length, and returns the
+    /// seconds left at the rate of the last window, or None if nothing was finished in it.
+    pub fn update(&mut self, elapsed: Duration, done: f64, total: f64) -> Option<f64> {
+        // Keeps the newest sample which is at least a window old, so the rate spans a window.
+        while self
+            .samples
+            .get(1)
+            .is_some_and(|&(time, _)| elapsed - time >= Self::WINDOW)
+        {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((elapsed, done));
+
+        let &(start, start_done) = self.samples.front()?;
+        let seconds = (elapsed - start).as_secs_f64();
+        let rate = (done - start_done) / seconds;
+        (seconds > 0.0 && rate > 0.0).then(|| (total - done).max(0.0) / rate)
+    }
+}
+
+/// Reports the progress of a decimal length every `interval` until `finished` is set.
+///
+/// `total` is the share of the search trees this session has to search, which the completion time
+/// is estimated from along with the rate shares were finished at recently.
+pub fn report_until(
+    finished: &AtomicBool,
+    progress: &Progress,
+    dec_length: u32,
+    total: f64,
+    interval: Duration,
+    config: &SearchConfig,
+    start_time: Instant,
+) {
+    let length_start = Instant::now();
+    let mut last_report = length_start;
+    let mut last_nodes = 0;
+    let mut eta = Eta::new();
+    while !finished.load(Ordering::Relaxed) {
+        if last_report.elapsed() < interval {
+            // Short naps so a finished length doesn't wait for a whole interval.
+            std::thread::sleep(interval.min(Duration::from_millis(100)));
+            continue;
+        }
+
+        let nodes = progress.nodes.load(Ordering::Relaxed);
+        let nodes_per_second = (nodes - last_nodes) as f64 / last_report.elapsed().as_secs_f64();
+        last_report = Instant::now();
+        last_nodes = nodes;
+
+        let done = progress.done().clamp(0.0, total);
+        let fraction = if total > 0.0 { done / total } else { 1.0 };
+        let eta_seconds = eta.update(length_start.elapsed(), done, total);
+        events::report(
+            Event::Progress {
+                dec_length,
+                nodes,
+                nodes_per_second,
+                pruned_interval: progress.pruned_interval.load(Ordering::Relaxed),
+                pruned_table: progress.pruned_table.load(Ordering::Relaxed),
+                levels: progress
+                    .levels
+                    .iter()
+                    .map(|count| count.swap(0, Ordering::Relaxed))
+                    .collect(),
+                done: fraction,
+                eta_seconds,
+            },
+            config,
+            start_time,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn first_report_has_no_rate() {
+        assert_eq!(Eta::new().update(seconds(1), 0.5, 1.0), None);
+    }
+
+    #[test]
+    fn pruning_at_the_start_is_left_out_after_a_window() {
+        let mut eta = Eta::new();
+        // Most of the tree is pruned before the first report, the rest takes 0.1% a second.
+        let mut done = 0.6;
+        let mut last = None;
+        for time in 1..=200 {
+            last = eta.update(seconds(time), done, 1.0);
+            done += 0.001;
+        }
+        let expected = (1.0 - (done - 0.001)) / 0.001;
+        let last = last.unwrap();
+        assert!((last - expected).abs() < 1e-6, "{last} != {expected}");
+    }
+
+    #[test]
+    fn stalled_progress_has_no_estimate() {
+        let mut eta = Eta::new();
+        eta.update(seconds(1), 0.0, 1.0);
+        assert!(eta.update(seconds(2), 0.596, 1.0).is_some());
+        let mut last = None;
+        for time in 3..100 {
+            last = eta.update(seconds(time), 0.596, 1.0);
+        }
+        assert_eq!(last, None);
+    }
+
+    #[test]
+    fn estimate_follows_the_recent_rate() {
+        let mut eta = Eta::new();
+        let mut done = 0.0;
+        let mut last = None;
+        // Fast at first, then ten times slower.
+        for time in 1..=300 {
+            done += if time <= 100 { 0.002 } else { 0.0002 };
+            last = eta.update(seconds(time), done, 1.0);
+        }
+        let expected = (1.0 - done) / 0.0002;
+        let last = last.unwrap();
+        assert!(
+            (last - expected).abs() / expected < 1e-6,
+            "{last} != {expected}"
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    OnceLock,
+};
+
+use crate::uint::UInt;
+
+// The decimal digits known at a given level fix the palindrome modulo 10^level, and therefore
+// also modulo 5^level, which is coprime to the binary digits. Other outer bases use their odd part.
+const MAX_MODULUS: u64 = 5u64.pow(8);
+const UNREACHABLE: u8 = u8::MAX;
+
+#[derive(Clone, Debug)]
+pub(crate) struct RemainderTable {
+    // For every remainder, the number of innermost binary digit pairs needed to reach it.
+    min_pairs: Vec<u8>,
+}
+
+impl RemainderTable {
+    fn new(bin_length: u32, modulus: u64) -> Self {
+        let pair_values: Vec<u64> = (0..bin_length.div_ceil(2))
+            .map(|i| {
+                let high = pow_mod(2, bin_length - 1 - i, modulus);
+                if bin_length - 1 - i == i {
+                    high
+                } else {
+                    (high + pow_mod(2, i, modulus)) % modulus
+                }
+            })
+            .collect();
+
+        let mut min_pairs = vec![UNREACHABLE; modulus as usize];
+        min_pairs[0] = 0;
+        let mut reached = vec![0u64];
+        for (i, &pair_value) in pair_values.iter().rev().enumerate() {
+            // Deep tables may need more pairs than fit, underestimating only weakens the pruning.
+            let pairs = (i + 1).min(UNREACHABLE as usize - 1) as u8;
+            for j in 0..reached.len() {
+                let mut new_remainder = reached[j] + pair_value;
+                if new_remainder >= modulus {
+                    new_remainder -= modulus;
+                }
+                if min_pairs[new_remainder as usize] == UNREACHABLE {
+                    min_pairs[new_remainder as usize] = pairs;
+                    reached.push(new_remainder);
+                }
+            }
+            if reached.len() as u64 == modulus {
+                break;
+            }
+        }
+
+        Self { min_pairs }
+    }
+
+    pub fn size(&self) -> usize {
+        self.min_pairs.len() * size_of::<u8>()
+    }
+}
+
+/// Prunes numbers whose remainder modulo powers of the outer base's odd factor can't be made up
+/// by the inner digit pairs still unknown, with a table for every binary length and modulus.
+#[derive(Debug)]
+pub struct RemainderTables {
+    min_bin_length: u32,
+    modulus_base: u64,
+    max_digits: u32,
+    // index is the number of unknown binary digit pairs.
+    wanted_digits: Vec<u32>,
+    // index is bin_length - min_bin_length, then the number of digits in the modulus minus one.
+    // Tables are only generated on first use, since many of them are never needed.
+    tables: Vec<Vec<OnceLock<RemainderTable>>>,
+    // index is the recursion level.
+    pruned: Vec<AtomicU64>,
+}
+
+impl RemainderTables {
+    /// The highest power of `modulus_base` whose tables for `num_bin_lengths` binary lengths fit in
+    /// `available_memory`, with their size in bytes. None if the tables can't prune anything.
+    pub fn calculate_memory_requirements(
+        num_bin_lengths: u32,
+        modulus_base: u32,
+        available_memory: u64,
+    ) -> Option<(u32, u64)> {
+        if modulus_base == 1 {
+            return None;
+        }
+
+        (1..=MAX_MODULUS.ilog(modulus_base as u64))
+            .rev()
+            .map(|max_digits| {
+                let size: u64 = (1..=max_digits).map(|i| (modulus_base as u64).pow(i)).sum();
+                (max_digits, size * num_bin_lengths as u64)
+            })
+            .find(|&(_, size)| size <= available_memory)
+    }
+
+    /// Empty tables for moduli up to `modulus_base`^`max_digits`, which are generated as they're
+    /// used. No tables if `max_digits` is 0.
+    pub fn new(
+        num_levels: usize,
+        min_bin_length: u32,
+        max_bin_length: u32,
+        modulus_base: u32,
+        max_digits: u32,
+    ) -> Self {
+        let modulus_base = modulus_base as u64;
+        // n unknown pairs reach at most 2^n remainders, so a smaller modulus tells us nothing.
+        let wanted_digits = (0..=max_bin_length / 2)
+            .filter(|_| max_digits > 0)
+            .map(|pairs| {
+                let mut digits = 0;
+                let mut remainders = 1f64;
+                while remainders < 2f64.powi(pairs as i32) {
+                    remainders *= modulus_base as f64;
+                    digits += 1;
+                }
+                digits
+            })
+            .collect();
+
+        Self {
+            min_bin_length,
+            modulus_base,
+            max_digits,
+            wanted_digits,
+            tables: (min_bin_length..=max_bin_length)
+                .map(|_| (0..max_digits).map(|_| OnceLock::new()).collect())
+                .collect(),
+            pruned: (0..num_levels).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    /// The size of the tables generated so far in bytes.
+    pub fn size(&self) -> usize {
+        self.tables
+            .iter()
+            .flatten()
+            .filter_map(OnceLock::get)
+            .map(RemainderTable::size)
+            .sum()
+    }
+
+    /// Whether a number with `known_digits` outer digits fixed at each end can still be a palindrome
+    /// `bin_length` bits long, given that its `msb_set_bits` highest bits are already known.
+    pub fn lookup<T: UInt>(
+        &self,
+        current_num: T,
+        msb_set_bits: i32,
+        known_digits: u32,
+        bin_length: u32,
+    ) -> bool {
+        let nonshared_bits = bin_length as i32 - msb_set_bits;
+        let unknown_pairs = nonshared_bits - (bin_length / 2) as i32;
+        if self.max_digits == 0 || msb_set_bits <= 0 || unknown_pairs < 0 {
+            return true;
+        }
+
+        let wanted_digits = self.wanted_digits[unknown_pairs as usize];
+        if known_digits < wanted_digits {
+            return true;
+        }
+
+        let digits = known_digits.min(wanted_digits + 4).min(self.max_digits);
+        if digits == 0 {
+            return true;
+        }
+
+        let modulus = self.modulus_base.pow(digits);
+        let table = self.tables[(bin_length - self.min_bin_length) as usize][digits as usize - 1]
+            .get_or_init(|| RemainderTable::new(bin_length, modulus));
+
+        let nonshared_bits = nonshared_bits as u32;
+        let shifted = current_num >> nonshared_bits;
+        let known_bits = (shifted << nonshared_bits)
+            | (shifted.reverse_bits() >> (T::BITS - msb_set_bits as u32));
+
+        let remainder = if current_num >= known_bits {
+            (current_num - known_bits).rem_u64(modulus)
+        } else {
+            (modulus - (known_bits - current_num).rem_u64(modulus)) % modulus
+        };
+
+        table.min_pairs[remainder as usize] as i32 <= unknown_pairs
+    }
+
+    /// Adds what one task pruned at every level.
+    pub fn record_pruned(&self, pruned: &[u64]) {
+        for (total, &count) in self.pruned.iter().zip(pruned) {
+            if count != 0 {
+                total.fetch_add(count, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// The number of branches pruned at every level.
+    pub fn pruned(&self) -> Vec<u64> {
+        self.pruned
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect()
+    }
+}
+
+fn pow_mod(base: u64, exponent: u32, modulus: u64) -> u64 {
+    let mut result = 1 % modulus;
+    let mut base = base % modulus;
+    let mut exponent = exponent;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result * base % modulus;
+        }
+        base = base * base % modulus;
+        exponent >>= 1;
+    }
+
+    result
+}
@@ -0,0 +1,543 @@
+use std::{collections::HashSet, fmt::Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checkpoint::fnv1a,
+    uint::{UInt, Widest},
+    SaveState, SaveTask, State,
+};
+
+/// Where a checkpoint split from a larger search came from, so the shards can be merged back.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Shard {
+    index: u32,
+    count: u32,
+    // The decimal length being searched when the checkpoint was split.
+    dec_length: u32,
+    // Shards stop after `dec_length`, the merged search continues up to this one.
+    max_dec_length: Option<u32>,
+    // The states handed to this shard. Everything it has left descends from one of them.
+    roots: Vec<SaveTask>,
+    // Hash of the roots of all the shards together.
+    origin: String,
+}
+
+// A binary length and the leading outer digits a state has fixed, which every state below it
+// starts with too. Sorted, a state comes right before the ones below it.
+type Key = (u32, Vec<u32>);
+
+fn key(bin_length: u32, state: &State<Widest>, outer: u32) -> Key {
+    // The trailing digits mirror the leading ones, and they're cheaper to get at, as many as fit
+    // in a word at a time.
+    let digits_per_word = u32::MAX.ilog(outer);
+    let word = (outer as u64).pow(digits_per_word);
+    let level = state.level as usize;
+    let mut digits = Vec::with_capacity(level);
+    let mut rest = state.current_num;
+    while digits.len() < level {
+        let mut chunk = rest.rem_u64(word);
+        for _ in 0..digits_per_word.min((level - digits.len()) as u32) {
+            digits.push((chunk % outer as u64) as u32);
+            chunk /= outer as u64;
+        }
+        if digits.len() < level {
+            rest /= Widest::from(word);
+        }
+    }
+
+    (bin_length, digits)
+}
+
+fn is_below(ancestor: &Key, key: &Key) -> bool {
+    ancestor.0 == key.0 && key.1.starts_with(&ancestor.1)
+}
+
+fn describe_prefix(digits: &[u32], outer: u32) -> String {
+    digits
+        .iter()
+        .fold(Widest::ZERO, |acc, &digit| {
+            acc * Widest::from(outer) + Widest::from(digit)
+        })
+        .to_string_radix(outer)
+}
+
+// The same as formatting with `{:x}`, which is slow for the widest integers.
+fn write_hex(out: &mut String, num: &Widest) {
+    let words = num.digits();
+    let highest = words.iter().rposition(|&word| word != 0).unwrap_or(0);
+    write!(out, "{:x}", words[highest]).unwrap();
+    for word in words[..highest].iter().rev() {
+        write!(out, "{word:016x}").unwrap();
+    }
+}
+
+fn origin(roots: &[(u32, &State<Widest>)]) -> String {
+    let mut keys: Vec<(u32, u32, &Widest, &Widest)> = roots
+        .iter()
+        .map(|&(bin_length, state)| (bin_length, state.level, &state.current_num, &state.bin_num))
+        .collect();
+    keys.sort_unstable();
+    let mut described = String::new();
+    for (bin_length, level, current_num, bin_num) in keys {
+        write!(described, "{bin_length},{level},").unwrap();
+        write_hex(&mut described, current_num);
+        described.push(',');
+        write_hex(&mut described, bin_num);
+        described.push(';');
+    }
+
+    format!("{:016x}", fnv1a(described.as_bytes()))
+}
+
+// Every state gets a task of its own, so a resumed shard searches them in parallel like the tasks
+// the search spawns.
+fn into_tasks(states: Vec<(u32, State<Widest>)>) -> Vec<SaveTask> {
+    states
+        .into_iter()
+        .map(|(bin_length, state)| SaveTask {
+            bin_length,
+            stack: vec![state],
+        })
+        .collect()
+}
+
+/// Splits the unfinished states of `save_state` into `count` checkpoints with about the same
+/// amount of work, which only search the current decimal length.
+pub fn split(save_state: SaveState, count: u32) -> Result<Vec<SaveState>, String> {
+    if save_state.shard.is_some() {
+        return Err("Checkpoint is already a shard, merge it first".to_string());
+    }
+    let states: Vec<(u32, State<Widest>)> = save_state
+        .tasks
+        .iter()
+        .flat_map(|task| {
+            task.stack
+                .iter()
+                .map(|state| (task.bin_length, state.clone()))
+        })
+        .collect();
+    if count == 0 || states.len() < count as usize {
+        return Err(format!(
+            "Can't split {} states into {count} shards, resume the search for a while first",
+            states.len()
+        ));
+    }
+
+    let origin = origin(
+        &states
+            .iter()
+            .map(|(bin_length, state)| (*bin_length, state))
+            .collect::<Vec<_>>(),
+    );
+
+    // The heaviest states go first, each to the shard with the least work so far.
+    let mut states = states;
+    states.sort_by(|(_, a), (_, b)| b.share.total_cmp(&a.share));
+    let mut loads = vec![0f64; count as usize];
+    let mut shards: Vec<Vec<(u32, State<Widest>)>> = (0..count).map(|_| vec![]).collect();
+    for (bin_length, state) in states {
+        let (lightest, _) = loads
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        loads[lightest] += state.share;
+        shards[lightest].push((bin_length, state));
+    }
+
+    Ok(shards
+        .into_iter()
+        .enumerate()
+        .map(|(index, states)| {
+            let tasks = into_tasks(states);
+            SaveState {
+                dec_length: save_state.dec_length,
+                bases: save_state.bases,
+                max_dec_length: Some(save_state.dec_length),
+                bin_lengths: save_state.bin_lengths.clone(),
+                completed_cells: save_state.completed_cells.clone(),
+                tasks: tasks.clone(),
+                palindromes_found: save_state.palindromes_found.clone(),
+                shard: Some(Shard {
+                    index: index as u32,
+                    count,
+                    dec_length: save_state.dec_length,
+                    max_dec_length: save_state.max_dec_length,
+                    roots: tasks,
+                    origin: origin.clone(),
+                }),
+            }
+        })
+        .collect())
+}
+
+/// Merges every shard split from one checkpoint back into a single search, checking that each
+/// remaining state belongs to exactly one shard and that no shard is missing.
+pub fn merge(shards: Vec<SaveState>) -> Result<SaveState, String> {
+    let Some(first) = shards.first() else {
+        return Err("No checkpoints to merge".to_string());
+    };
+    let Some(info) = first.shard.clone() else {
+        return Err("Checkpoint isn't a shard".to_string());
+    };
+    let bases = first.bases;
+    let bin_lengths = first.bin_lengths.clone();
+
+    let mut indices = HashSet::new();
+    for save_state in &shards {
+        let Some(shard) = &save_state.shard else {
+            return Err("Checkpoint isn't a shard".to_string());
+        };
+        if shard.origin != info.origin
+            || shard.count != info.count
+            || save_state.bases != bases
+            || save_state.bin_lengths != bin_lengths
+        {
+            return Err(format!(
+                "Shard {} was split from a different checkpoint",
+                shard.index
+            ));
+        }
+        if !indices.insert(shard.index) {
+            return Err(format!("Shard {} appears more than once", shard.index));
+        }
+        // A shard done with its states moves on to the next length and stops there.
+        if save_state.dec_length != info.dec_length
+            && !(save_state.dec_length == info.dec_length + 1 && save_state.tasks.is_empty())
+        {
+            return Err(format!(
+                "Shard {} searched past decimal length {}",
+                shard.index, info.dec_length
+            ));
+        }
+    }
+    if indices.len() != info.count as usize {
+        return Err(format!(
+            "Only {} of {} shards were given",
+            indices.len(),
+            info.count
+        ));
+    }
+
+    let roots: Vec<(u32, &State<Widest>)> = shards
+        .iter()
+        .flat_map(|save_state| &save_state.shard.as_ref().unwrap().roots)
+        .flat_map(|task| task.stack.iter().map(|state| (task.bin_length, state)))
+        .collect();
+    if origin(&roots) != info.origin {
+        return Err(
+            "The shards' states don't add up to the checkpoint they were split from".into(),
+        );
+    }
+
+    // Every state left must descend from its own shard's roots, and no state from another.
+    let dec_length = info.dec_length;
+    let mut keys = vec![];
+    for save_state in &shards {
+        let shard = save_state.shard.as_ref().unwrap();
+        let mut roots: Vec<Key> = shard
+            .roots
+            .iter()
+            .flat_map(|task| {
+                task.stack
+                    .iter()
+                    .map(|state| key(task.bin_length, state, bases.outer))
+            })
+            .collect();
+        roots.sort_unstable();
+        for task in &save_state.tasks {
+            for state in &task.stack {
+                let state_key = key(task.bin_length, state, bases.outer);
+                // The roots don't overlap, so only the last one up to the state can be above it.
+                let after_root = roots.partition_point(|root| *root <= state_key);
+                if after_root == 0 || !is_below(&roots[after_root - 1], &state_key) {
+                    return Err(format!(
+                        "Shard {} has a state outside the ones it was given",
+                        shard.index
+                    ));
+                }
+                keys.push(state_key);
+            }
+        }
+    }
+    keys.sort_unstable();
+    for pair in keys.windows(2) {
+        if is_below(&pair[0], &pair[1]) {
+            let (bin_length, digits) = &pair[1];
+            return Err(format!(
+                "The prefix {} of binary length {bin_length} is searched twice",
+                describe_prefix(digits, bases.outer)
+            ));
+        }
+    }
+
+    let tasks: Vec<SaveTask> = shards
+        .iter()
+        .flat_map(|save_state| save_state.tasks.iter().cloned())
+        .collect();
+    // Shards mark a binary length complete once their part of it is done.
+    let mut completed_cells = vec![];
+    for save_state in &shards {
+        for &cell in &save_state.completed_cells {
+            let still_searched = cell.dec_length == dec_length
+                && tasks.iter().any(|task| task.bin_length == cell.bin_length);
+            if !still_searched && !completed_cells.contains(&cell) {
+                completed_cells.push(cell);
+            }
+        }
+    }
+    let mut palindromes_found: Vec<Widest> = shards
+        .iter()
+        .flat_map(|save_state| save_state.palindromes_found.iter().copied())
+        .collect();
+    palindromes_found.sort_unstable();
+    palindromes_found.dedup();
+
+    Ok(SaveState {
+        dec_length: if tasks.is_empty() {
+            dec_length + 1
+        } else {
+            dec_length
+        },
+        bases,
+        max_dec_length: info.max_dec_length,
+        bin_lengths,
+        completed_cells,
+        tasks,
+        palindromes_found,
+        shard: None,
+    })
+}
+
+/// Describes a shard for `inspect`.
+pub fn describe(shard: &Shard) -> String {
+    format!(
+        "{} of {}, split at decimal length {}",
+        shard.index + 1,
+        shard.count,
+        shard.dec_length
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bases::Bases;
+
+    const DEC_LENGTH: u32 = 10;
+    const BIN_LENGTH: u32 = 34;
+
+    // The state with `digits` fixed at both ends.
+    fn state(digits: &[u32]) -> State<Widest> {
+        let ten = Widest::from(10u32);
+        let current_num =
+            digits
+                .iter()
+                .enumerate()
+                .fold(Widest::ZERO, |acc, (position, &digit)| {
+                    let digit = Widest::from(digit);
+                    acc + digit * ten.pow(DEC_LENGTH - 1 - position as u32)
+                        + digit * ten.pow(position as u32)
+                });
+        State {
+            current_num,
+            bin_num: Widest::ZERO,
+            is_odd: None,
+            level: digits.len() as u32,
+            share: 0.1f64.powi(digits.len() as i32),
+        }
+    }
+
+    fn checkpoint() -> SaveState {
+        SaveState {
+            tasks: vec![SaveTask {
+                bin_length: BIN_LENGTH,
+                stack: [1, 3, 5, 7, 9]
+                    .iter()
+                    .map(|&digit| state(&[digit]))
+                    .collect(),
+            }],
+            ..SaveState::new(Bases::default(), DEC_LENGTH, Some(12), None)
+        }
+    }
+
+    fn keys(save_state: &SaveState) -> Vec<Key> {
+        let mut keys: Vec<Key> = save_state
+            .tasks
+            .iter()
+            .flat_map(|task| {
+                task.stack
+                    .iter()
+                    .map(|state| key(task.bin_length, state, 10))
+            })
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    // The leading digit of every state in a shard's tasks.
+    fn leading_digits(save_state: &SaveState) -> Vec<u32> {
+        keys(save_state)
+            .iter()
+            .map(|(_, digits)| digits[0])
+            .collect()
+    }
+
+    #[test]
+    fn hex_matches_the_formatter() {
+        let nums = [
+            Widest::ZERO,
+            Widest::ONE,
+            Widest::from(u64::MAX),
+            Widest::ONE << 64,
+            Widest::from(0xabcdefu32) << 700,
+            Widest::MAX,
+        ];
+        for num in nums {
+            let mut hex = String::new();
+            write_hex(&mut hex, &num);
+            assert_eq!(hex, format!("{num:x}"));
+        }
+    }
+
+    #[test]
+    fn key_is_the_leading_digits() {
+        assert!(key(BIN_LENGTH, &state(&[]), 10).1.is_empty());
+        assert_eq!(key(BIN_LENGTH, &state(&[9, 0, 7]), 10).1, vec![9, 0, 7]);
+        // The middle of an odd length is fixed by both ends at once.
+        let middle = State {
+            current_num: Widest::from(12321u32),
+            level: 3,
+            ..state(&[])
+        };
+        assert_eq!(key(BIN_LENGTH, &middle, 10).1, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn split_balances_the_states() {
+        let shards = split(checkpoint(), 3).unwrap();
+        let mut digits: Vec<u32> = shards.iter().flat_map(leading_digits).collect();
+        digits.sort_unstable();
+        assert_eq!(digits, [1, 3, 5, 7, 9]);
+        assert!(shards
+            .iter()
+            .all(|shard| (1..=2).contains(&shard.tasks.len())));
+        assert!(split(shards.into_iter().next().unwrap(), 2).is_err());
+        assert!(split(checkpoint(), 6).is_err());
+    }
+
+    #[test]
+    fn unchanged_shards_merge_back() {
+        let merged = merge(split(checkpoint(), 3).unwrap()).unwrap();
+        assert_eq!(keys(&merged), keys(&checkpoint()));
+        assert_eq!(merged.dec_length, DEC_LENGTH);
+        assert_eq!(merged.max_dec_length, Some(12));
+        assert!(merged.shard.is_none());
+    }
+
+    #[test]
+    fn searched_shards_merge_back() {
+        let mut shards = split(checkpoint(), 2).unwrap();
+        // One shard expanded a root into its children, the other finished one of its roots.
+        let root = shards[0].tasks.remove(0).stack.remove(0);
+        let leading = key(BIN_LENGTH, &root, 10).1[0];
+        shards[0].tasks.push(SaveTask {
+            bin_length: BIN_LENGTH,
+            stack: (0..10).map(|digit| state(&[leading, digit])).collect(),
+        });
+        shards[1].tasks.pop();
+
+        let expected: Vec<Key> = shards.iter().flat_map(keys).collect();
+        let merged = merge(shards).unwrap();
+        let mut expected = expected;
+        expected.sort_unstable();
+        assert_eq!(keys(&merged), expected);
+    }
+
+    #[test]
+    fn finished_shards_move_on() {
+        let shards: Vec<SaveState> = split(checkpoint(), 2)
+            .unwrap()
+            .into_iter()
+            .map(|shard| SaveState {
+                dec_length: DEC_LENGTH + 1,
+                tasks: vec![],
+                palindromes_found: vec![Widest::from(shard.shard.as_ref().unwrap().index)],
+                ..shard
+            })
+            .collect();
+        let merged = merge(shards).unwrap();
+        assert_eq!(merged.dec_length, DEC_LENGTH + 1);
+        assert!(merged.tasks.is_empty());
+        assert_eq!(merged.palindromes_found, [Widest::ZERO, Widest::ONE]);
+    }
+
+    #[test]
+    fn repeated_shard_is_rejected() {
+        let mut shards = split(checkpoint(), 3).unwrap();
+        shards[2] = split(checkpoint(), 3).unwrap().remove(1);
+        let error = merge(shards).err().unwrap();
+        assert!(error.contains("more than once"), "{error}");
+    }
+
+    #[test]
+    fn missing_shard_is_rejected() {
+        let mut shards = split(checkpoint(), 3).unwrap();
+        shards.pop();
+        let error = merge(shards).err().unwrap();
+        assert!(error.contains("Only 2 of 3"), "{error}");
+    }
+
+    #[test]
+    fn shard_from_another_split_is_rejected() {
+        let mut shards = split(checkpoint(), 2).unwrap();
+        let mut other = checkpoint();
+        other.tasks[0].stack.pop();
+        shards[1] = split(other, 2).unwrap().remove(1);
+        let error = merge(shards).err().unwrap();
+        assert!(error.contains("different checkpoint"), "{error}");
+    }
+
+    #[test]
+    fn state_of_another_shard_is_rejected() {
+        let mut shards = split(checkpoint(), 2).unwrap();
+        let moved = shards[1].tasks.remove(0);
+        shards[0].tasks.push(moved);
+        let error = merge(shards).err().unwrap();
+        assert!(error.contains("outside the ones it was given"), "{error}");
+    }
+
+    #[test]
+    fn state_searched_twice_is_rejected() {
+        let mut shards = split(checkpoint(), 2).unwrap();
+        // A child of a root which is still there itself.
+        let root = &shards[0].tasks[0].stack[0];
+        let leading = key(BIN_LENGTH, root, 10).1[0];
+        shards[0].tasks.push(SaveTask {
+            bin_length: BIN_LENGTH,
+            stack: vec![state(&[leading, 4])],
+        });
+        let error = merge(shards).err().unwrap();
+        assert!(
+            error.contains(&format!(
+                "prefix {leading}4 of binary length {BIN_LENGTH} is searched twice"
+            )),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn same_prefix_in_another_binary_length_is_kept_apart() {
+        let with_another_bin_length = || {
+            let mut save_state = checkpoint();
+            save_state.tasks.push(SaveTask {
+                bin_length: BIN_LENGTH - 1,
+                stack: vec![state(&[1]), state(&[3])],
+            });
+            save_state
+        };
+        let merged = merge(split(with_another_bin_length(), 3).unwrap()).unwrap();
+        assert_eq!(keys(&merged), keys(&with_another_bin_length()));
+    }
+}
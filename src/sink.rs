@@ -0,0 +1,176 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{mpsc, Mutex},
+};
+
+use serde::Serialize;
+
+use crate::{events::Event, uint::Widest};
+
+/// Receives what a search finds and does, e.g. to store palindromes somewhere of its own.
+///
+/// Implement the methods of the events of interest, or `report` to get all of them. It's called
+/// from every thread of the search, so it has to lock whatever it changes.
+pub trait PalindromeSink: Send + Sync {
+    /// A palindrome was found, with its lengths in the outer and inner base.
+    fn found(&self, _value: Widest, _dec_length: u32, _bin_length: u32) {}
+
+    /// The search of a decimal length started, or resumed from a checkpoint.
+    fn length_started(&self, _dec_length: u32) {}
+
+    /// `nodes` is how many states were expanded searching the length in this session.
+    fn length_finished(&self, _dec_length: u32, _nodes: u64) {}
+
+    /// A checkpoint with `tasks` tasks left was written to `path`.
+    fn checkpoint_written(&self, _path: &Path, _tasks: usize) {}
+
+    /// Called with every event and the seconds since the search started. Passes the events above
+    /// on to their methods and drops the rest, unless overridden.
+    ///
+    /// An error stops the search, which then fails with it.
+    fn report(&self, event: &Event, _elapsed: f32) -> io::Result<()> {
+        match *event {
+            Event::Found {
+                value,
+                dec_length,
+                bin_length,
+                ..
+            } => self.found(value, dec_length, bin_length),
+            Event::LengthStarted { dec_length } => self.length_started(dec_length),
+            Event::LengthFinished { dec_length, nodes } => self.length_finished(dec_length, nodes),
+            Event::CheckpointWritten { ref path, tasks } => self.checkpoint_written(path, tasks),
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Prints "time: number" lines, and the other events too when verbose. Warnings go to stderr.
+#[derive(Default)]
+pub struct StdoutSink {
+    /// Prints every event, not just the palindromes and the progress.
+    pub verbose: bool,
+}
+
+impl PalindromeSink for StdoutSink {
+    fn report(&self, event: &Event, elapsed: f32) -> io::Result<()> {
+        if let Event::Warning { .. } = event {
+            writeln!(io::stderr(), "{}", event.to_text(elapsed))
+        } else if self.verbose
+            || matches!(
+                event,
+                Event::Found { .. } | Event::Progress { .. } | Event::LevelStats { .. }
+            )
+        {
+            writeln!(io::stdout(), "{}", event.to_text(elapsed))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    elapsed: f32,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Writes every event as a JSON object on its own line.
+pub struct JsonLinesSink<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    /// Writes the events to `writer`, flushing it after every one.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+impl JsonLinesSink<io::Stdout> {
+    /// Writes the events to stdout, like the binary does with `--format json`.
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write + Send> PalindromeSink for JsonLinesSink<W> {
+    fn report(&self, event: &Event, elapsed: f32) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        serde_json::to_writer(&mut *writer, &Line { elapsed, event })?;
+        // Flushed line by line so whatever reads them sees every event as it happens.
+        writeln!(writer)?;
+        writer.flush()
+    }
+}
+
+/// Appends the palindromes to a file in the "time: number" format of the results directory.
+pub struct FileSink {
+    file: Mutex<File>,
+}
+
+impl FileSink {
+    /// Opens `path` for appending, creating it if it doesn't exist.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl PalindromeSink for FileSink {
+    fn report(&self, event: &Event, elapsed: f32) -> io::Result<()> {
+        if let Event::Found { .. } = event {
+            // Unbuffered, so a killed search loses nothing it found.
+            writeln!(self.file.lock().unwrap(), "{}", event.to_text(elapsed))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps the palindromes in memory.
+#[derive(Default)]
+pub struct MemorySink {
+    palindromes: Mutex<Vec<Widest>>,
+}
+
+impl MemorySink {
+    /// The palindromes found so far, in the order they were found.
+    pub fn palindromes(&self) -> Vec<Widest> {
+        self.palindromes.lock().unwrap().clone()
+    }
+}
+
+impl PalindromeSink for MemorySink {
+    fn found(&self, value: Widest, _dec_length: u32, _bin_length: u32) {
+        self.palindromes.lock().unwrap().push(value);
+    }
+}
+
+/// Sends every event over a channel, e.g. to another thread.
+pub struct ChannelSink {
+    sender: mpsc::Sender<Event>,
+}
+
+impl ChannelSink {
+    /// Sends the events to `sender`, dropping them once the receiver is gone.
+    pub fn new(sender: mpsc::Sender<Event>) -> Self {
+        Self { sender }
+    }
+}
+
+impl PalindromeSink for ChannelSink {
+    fn report(&self, event: &Event, _elapsed: f32) -> io::Result<()> {
+        // Nobody's listening anymore if the receiver is gone, so the event is just dropped.
+        let _ = self.sender.send(event.clone());
+        Ok(())
+    }
+}
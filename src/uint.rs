@@ -0,0 +1,437 @@
+use std::{
+    fmt::{Debug, Display, LowerHex},
+    ops::{Add, AddAssign, BitAnd, BitOr, BitXor, Div, Mul, Rem, Shl, Shr, Sub},
+};
+
+use ethnum::u256;
+
+use crate::Bits;
+
+/// A 512-bit unsigned integer.
+pub type U512 = bnum::types::U512;
+/// A 1024-bit unsigned integer.
+pub type U1024 = bnum::types::U1024;
+
+/// The widest supported integer, used wherever numbers searched at different widths are kept
+/// together, e.g. in the save state.
+pub type Widest = U1024;
+
+/// A fixed width unsigned integer the search can run on.
+pub trait UInt:
+    Copy
+    + Ord
+    + Debug
+    + Display
+    + LowerHex
+    + Send
+    + Sync
+    + 'static
+    + Add<Output = Self>
+    + AddAssign
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Rem<Output = Self>
+    + Shl<u32, Output = Self>
+    + Shr<u32, Output = Self>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + Bits
+{
+    /// The width in bits.
+    const BITS: u32;
+    /// 0.
+    const ZERO: Self;
+    /// 1.
+    const ONE: Self;
+
+    /// `num` widened.
+    fn from_u64(num: u64) -> Self;
+    /// The lowest 64 bits.
+    fn low_u64(self) -> u64;
+    /// The number of zeros above the highest set bit.
+    fn leading_zeros(self) -> u32;
+    /// The bits in reverse order, over the whole width.
+    fn reverse_bits(self) -> Self;
+    /// `self` to the power of `exponent`, which mustn't overflow.
+    fn pow(self, exponent: u32) -> Self;
+    /// The product, or None if it overflows.
+    fn checked_mul(self, other: Self) -> Option<Self>;
+    /// Parses digits in `radix`, None if they're invalid or don't fit.
+    fn from_str_radix(src: &str, radix: u32) -> Option<Self>;
+
+    /// The same number as a `Widest`.
+    fn to_widest(self) -> Widest {
+        (0..Self::BITS / 64).fold(Widest::ZERO, |acc, i| {
+            acc | Widest::from((self >> (i * 64)).low_u64()) << (i * 64)
+        })
+    }
+
+    /// Truncates `num` if it doesn't fit.
+    fn from_widest(num: Widest) -> Self {
+        (0..Self::BITS / 64).fold(Self::ZERO, |acc, i| {
+            acc | Self::from_u64((num >> (i * 64)).low_u64()) << (i * 64)
+        })
+    }
+
+    /// Digits past 9 are letters, or for radixes above 36, every digit is written in base 10 and
+    /// separated by ':'.
+    fn to_string_radix(self, radix: u32) -> String {
+        let mut digits = vec![];
+        let mut rest = self;
+        let radix_t = Self::from_u64(radix as u64);
+        loop {
+            digits.push(rest.rem_u64(radix as u64) as u32);
+            rest = rest / radix_t;
+            if rest == Self::ZERO {
+                break;
+            }
+        }
+
+        if radix > 36 {
+            let digits: Vec<String> = digits.iter().rev().map(u32::to_string).collect();
+            return digits.join(":");
+        }
+        digits
+            .iter()
+            .rev()
+            .map(|&digit| char::from_digit(digit, radix).unwrap())
+            .collect()
+    }
+
+    /// The remainder modulo `modulus`, which must be at most 2^32. The generic remainder is slow,
+    /// but a small modulus allows going word by word.
+    fn rem_u64(self, modulus: u64) -> u64 {
+        let word_modulus = (u64::MAX % modulus + 1) % modulus;
+        (0..Self::BITS / 64).rev().fold(0, |acc, i| {
+            (acc * word_modulus + (self >> (i * 64)).low_u64() % modulus) % modulus
+        })
+    }
+}
+
+macro_rules! impl_uint {
+    ($type:ty, $zero:expr, $one:expr, $from_u64:expr, $low_u64:expr) => {
+        impl Bits for $type {
+            fn bits(&self) -> u32 {
+                <$type>::BITS - <$type>::leading_zeros(*self)
+            }
+        }
+
+        impl UInt for $type {
+            const BITS: u32 = <$type>::BITS;
+            const ZERO: Self = $zero;
+            const ONE: Self = $one;
+
+            #[inline]
+            fn from_u64(num: u64) -> Self {
+                $from_u64(num)
+            }
+
+            #[inline]
+            fn low_u64(self) -> u64 {
+                $low_u64(self)
+            }
+
+            #[inline]
+            fn leading_zeros(self) -> u32 {
+                <$type>::leading_zeros(self)
+            }
+
+            #[inline]
+            fn reverse_bits(self) -> Self {
+                <$type>::reverse_bits(self)
+            }
+
+            fn pow(self, exponent: u32) -> Self {
+                <$type>::pow(self, exponent)
+            }
+
+            fn checked_mul(self, other: Self) -> Option<Self> {
+                <$type>::checked_mul(self, other)
+            }
+
+            fn from_str_radix(src: &str, radix: u32) -> Option<Self> {
+                <$type>::from_str_radix(src, radix).ok()
+            }
+        }
+    };
+}
+
+impl_uint!(u64, 0, 1, u64::from, |num: u64| num);
+impl_uint!(u128, 0, 1, u128::from, |num: u128| num as u64);
+impl_uint!(
+    u256,
+    u256::ZERO,
+    u256::ONE,
+    u256::from,
+    |num: u256| *num.low() as u64
+);
+impl_uint!(U512, U512::ZERO, U512::ONE, U512::from, |num: U512| num
+    .digits()[0]);
+impl_uint!(U1024, U1024::ZERO, U1024::ONE, U1024::from, |num: U1024| {
+    num.digits()[0]
+});
+
+/// Evaluates `$body` with `$uint` standing for the narrowest integer type with `$bits` bits, which
+/// is what the search runs on since narrower integers are faster.
+macro_rules! with_uint {
+    ($bits:expr, $uint:ident => $body:expr) => {
+        match $bits {
+            Some(0..=64) => {
+                type $uint = u64;
+                $body
+            }
+            Some(65..=128) => {
+                type $uint = u128;
+                $body
+            }
+            Some(129..=256) => {
+                type $uint = ::ethnum::u256;
+                $body
+            }
+            Some(257..=512) => {
+                type $uint = $crate::uint::U512;
+                $body
+            }
+            Some(513..=1024) => {
+                type $uint = $crate::uint::U1024;
+                $body
+            }
+            bits => panic!("{bits:?} bits don't fit in the widest supported integer"),
+        }
+    };
+}
+pub(crate) use with_uint;
+
+/// Serializes integers of any width as "0x" prefixed hex strings, like `ethnum` does for u256.
+/// Binary formats get the little-endian bytes without the trailing zeros instead.
+pub mod hex {
+    use std::{fmt, marker::PhantomData};
+
+    use serde::{
+        de::{Error, Visitor},
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    use super::UInt;
+
+    /// Serializes a single integer.
+    pub fn serialize<T: UInt, S: Serializer>(num: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return serializer.serialize_str(&format!("{num:#x}"));
+        }
+
+        let mut bytes: Vec<u8> = (0..T::BITS / 8)
+            .map(|i| (*num >> (i * 8)).low_u64() as u8)
+            .collect();
+        while bytes.last() == Some(&0) {
+            bytes.pop();
+        }
+        serializer.serialize_bytes(&bytes)
+    }
+
+    /// Deserializes a single integer.
+    pub fn deserialize<'de, T: UInt, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        if deserializer.is_human_readable() {
+            let hex = String::deserialize(deserializer)?;
+            return parse(&hex)
+                .ok_or_else(|| D::Error::custom(format!("invalid hex number: {hex}")));
+        }
+
+        deserializer.deserialize_bytes(BytesVisitor(PhantomData))
+    }
+
+    fn parse<T: UInt>(hex: &str) -> Option<T> {
+        T::from_str_radix(hex.strip_prefix("0x")?, 16)
+    }
+
+    struct BytesVisitor<T>(PhantomData<T>);
+
+    impl<T: UInt> Visitor<'_> for BytesVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "at most {} little-endian bytes", T::BITS / 8)
+        }
+
+        fn visit_bytes<E: Error>(self, bytes: &[u8]) -> Result<T, E> {
+            if bytes.len() > (T::BITS / 8) as usize {
+                return Err(E::invalid_length(bytes.len(), &self));
+            }
+
+            Ok(bytes.iter().enumerate().fold(T::ZERO, |acc, (i, &byte)| {
+                acc | T::from_u64(byte as u64) << (i as u32 * 8)
+            }))
+        }
+    }
+
+    struct Wrapper<T>(T);
+
+    impl<T: UInt> Serialize for Wrapper<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize(&self.0, serializer)
+        }
+    }
+
+    impl<'de, T: UInt> Deserialize<'de> for Wrapper<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserialize(deserializer).map(Wrapper)
+        }
+    }
+
+    /// The same for vectors of integers.
+    pub mod vec {
+        use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serializer};
+
+        use super::{super::UInt, Wrapper};
+
+        /// Serializes the integers as a sequence.
+        pub fn serialize<T: UInt, S: Serializer>(
+            nums: &[T],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(Some(nums.len()))?;
+            for &num in nums {
+                seq.serialize_element(&Wrapper(num))?;
+            }
+            seq.end()
+        }
+
+        /// Deserializes a sequence of integers.
+        pub fn deserialize<'de, T: UInt, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<T>, D::Error> {
+            Ok(Vec::<Wrapper<T>>::deserialize(deserializer)?
+                .into_iter()
+                .map(|Wrapper(num)| num)
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Wrapped<T: UInt> {
+        #[serde(with = "hex")]
+        num: T,
+        #[serde(with = "hex::vec")]
+        nums: Vec<T>,
+    }
+
+    // Numbers at the edges of the 64-bit words and of the whole width.
+    fn boundaries<T: UInt>() -> Vec<T> {
+        let high_bit = T::ONE << (T::BITS - 1);
+        let max = high_bit - T::ONE + high_bit;
+        let mut nums = vec![T::ZERO, T::ONE, max, high_bit, max - T::ONE];
+        for word in 1..T::BITS / 64 {
+            let word_start = T::ONE << (word * 64);
+            nums.extend([word_start - T::ONE, word_start, word_start + T::ONE]);
+        }
+        nums.push(
+            T::from_str_radix(&"9".repeat((T::BITS as f64 * 2f64.log10()) as usize), 10).unwrap(),
+        );
+        nums
+    }
+
+    fn check_width<T: UInt>() {
+        for num in boundaries::<T>() {
+            let widest = num.to_widest();
+            assert_eq!(
+                widest,
+                Widest::from_str_radix(&format!("{num:x}"), 16).unwrap()
+            );
+            assert_eq!(T::from_widest(widest), num);
+            assert_eq!(num.to_string(), widest.to_string());
+            for modulus in [2, 3, 5, 10, 625, 5u64.pow(8), u32::MAX as u64, 1 << 32] {
+                assert_eq!(
+                    num.rem_u64(modulus),
+                    (widest % Widest::from(modulus)).low_u64(),
+                    "{num} % {modulus}"
+                );
+            }
+            for radix in [2, 10, 36, 1000] {
+                assert_eq!(num.to_string_radix(radix), widest.to_string_radix(radix));
+            }
+        }
+        // Wider numbers lose their high words.
+        let max = boundaries::<Widest>()[2];
+        assert_eq!(T::from_widest(max), boundaries::<T>()[2]);
+    }
+
+    fn check_serde<T: UInt>() {
+        let nums = boundaries::<T>();
+        for &num in &nums {
+            let wrapped = Wrapped {
+                num,
+                nums: nums.clone(),
+            };
+            let json = serde_json::to_string(&wrapped).unwrap();
+            assert_eq!(serde_json::from_str::<Wrapped<T>>(&json).unwrap(), wrapped);
+            let bytes = bincode::serialize(&wrapped).unwrap();
+            assert_eq!(bincode::deserialize::<Wrapped<T>>(&bytes).unwrap(), wrapped);
+        }
+
+        // A number written at the widest width doesn't fit in a narrower one.
+        if T::BITS < Widest::BITS {
+            let json = format!(
+                r#"{{"num":"{:#x}","nums":[]}}"#,
+                T::ONE.to_widest() << T::BITS
+            );
+            assert!(serde_json::from_str::<Wrapped<T>>(&json).is_err());
+            let too_wide = Wrapped {
+                num: Widest::ONE << T::BITS,
+                nums: vec![],
+            };
+            let bytes = bincode::serialize(&too_wide).unwrap();
+            assert!(bincode::deserialize::<Wrapped<T>>(&bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn every_width_round_trips_through_the_widest() {
+        check_width::<u64>();
+        check_width::<u128>();
+        check_width::<u256>();
+        check_width::<U512>();
+        check_width::<U1024>();
+    }
+
+    #[test]
+    fn every_width_round_trips_through_hex() {
+        check_serde::<u64>();
+        check_serde::<u128>();
+        check_serde::<u256>();
+        check_serde::<U512>();
+        check_serde::<U1024>();
+    }
+
+    #[test]
+    fn widths_are_picked_at_their_boundaries() {
+        for (bits, expected) in [
+            (1, 64),
+            (64, 64),
+            (65, 128),
+            (128, 128),
+            (129, 256),
+            (256, 256),
+            (257, 512),
+            (512, 512),
+            (513, 1024),
+            (1024, 1024),
+        ] {
+            assert_eq!(with_uint!(Some(bits), T => T::BITS), expected);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "don't fit")]
+    fn too_many_bits_panic() {
+        with_uint!(Some(1025), T => T::BITS);
+    }
+}
@@ -0,0 +1,372 @@
+use std::{fs, io, path::Path, sync::Mutex, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bases::{BaseLayout, Bases},
+    bin_length_range, find_palindrome_recursive, generate_tables, get_digit_cache,
+    get_max_bin_cache, get_max_cache, initial_state,
+    par_bitmap_table::LookupTable,
+    progress::Progress,
+    remainder_table::RemainderTables,
+    uint::{self, with_uint, UInt, Widest},
+    SaveState, SearchConfig, State,
+};
+
+/// A part of a search small enough to be run anywhere: the numbers of one decimal and binary
+/// length whose leading outer digits fall in a range.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct WorkUnit {
+    /// The bases searched.
+    pub bases: Bases,
+    /// The decimal length of the numbers.
+    pub dec_length: u32,
+    /// The binary length of the numbers.
+    pub bin_length: u32,
+    /// The number of leading outer digits in a prefix, 0 for the whole binary length.
+    pub prefix_digits: u32,
+    /// Prefixes are read as numbers in the outer base, from `first_prefix` to `last_prefix`.
+    pub first_prefix: u64,
+    /// The last prefix in the unit, included.
+    pub last_prefix: u64,
+}
+
+/// What running a `WorkUnit` found.
+#[derive(Serialize, Deserialize)]
+pub struct UnitResult {
+    /// The unit run.
+    pub unit: WorkUnit,
+    /// The palindromes found, sorted.
+    #[serde(with = "uint::hex::vec")]
+    pub palindromes: Vec<Widest>,
+    /// The number of search states expanded.
+    pub nodes: u64,
+    /// How long the unit took.
+    pub seconds: f32,
+}
+
+// Every level of the search fixes one outer digit at each end, except the last one.
+fn num_levels(dec_length: u32) -> u32 {
+    dec_length.div_ceil(2)
+}
+
+// The prefixes with `prefix_digits` digits, which can't start with a zero.
+fn prefix_range(outer: u32, prefix_digits: u32) -> Option<(u64, u64)> {
+    if prefix_digits == 0 {
+        return Some((0, 0));
+    }
+
+    let outer = outer as u64;
+    Some((
+        outer.checked_pow(prefix_digits - 1)?,
+        outer.checked_pow(prefix_digits)? - 1,
+    ))
+}
+
+impl WorkUnit {
+    /// Checks that the unit's numbers fit in the widest integer and that its binary length and
+    /// prefixes exist at its decimal length.
+    pub fn validate(&self) -> Result<(), String> {
+        self.bases.validate()?;
+        self.bases.check_fits(self.dec_length)?;
+        let layout = BaseLayout::<Widest>::new(self.bases, self.dec_length);
+        let (min_bin_length, max_bin_length) = bin_length_range(&layout, self.dec_length);
+        if !(min_bin_length..=max_bin_length).contains(&self.bin_length) {
+            return Err(format!(
+                "Decimal length {} has no binary length {}",
+                self.dec_length, self.bin_length
+            ));
+        }
+        // States at the last level are leaves, so prefixes have to stop before it.
+        if self.prefix_digits > 0 && self.prefix_digits >= num_levels(self.dec_length) {
+            return Err(format!(
+                "Prefixes of decimal length {} have less than {} digits",
+                self.dec_length, self.prefix_digits
+            ));
+        }
+        let (first, last) = prefix_range(self.bases.outer, self.prefix_digits)
+            .ok_or_else(|| format!("Prefixes of {} digits don't fit", self.prefix_digits))?;
+        if self.first_prefix < first
+            || self.last_prefix > last
+            || self.first_prefix > self.last_prefix
+        {
+            return Err(format!(
+                "Prefix range {}..={} is outside {first}..={last}",
+                self.first_prefix, self.last_prefix
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads one unit per line from `path`, or from stdin if it's "-".
+pub fn read(path: &Path) -> io::Result<Vec<WorkUnit>> {
+    let contents = if path.as_os_str() == "-" {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(path)?
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// Splits every wanted binary length of `dec_length` into `count` units of about as many
+/// prefixes.
+pub fn export(
+    bases: Bases,
+    dec_length: u32,
+    bin_lengths: Option<&[u32]>,
+    prefix_digits: u32,
+    count: u64,
+) -> Result<Vec<WorkUnit>, String> {
+    bases.validate()?;
+    bases.check_fits(dec_length)?;
+    let (first, last) = prefix_range(bases.outer, prefix_digits)
+        .ok_or_else(|| format!("Prefixes of {prefix_digits} digits don't fit"))?;
+    let num_prefixes = last - first + 1;
+    if count == 0 || count > num_prefixes {
+        return Err(format!(
+            "Can't split {num_prefixes} prefixes into {count} units"
+        ));
+    }
+
+    let layout = BaseLayout::<Widest>::new(bases, dec_length);
+    let (min_bin_length, max_bin_length) = bin_length_range(&layout, dec_length);
+    let mut units = vec![];
+    for bin_length in min_bin_length..=max_bin_length {
+        if bin_lengths.is_some_and(|bin_lengths| !bin_lengths.contains(&bin_length)) {
+            continue;
+        }
+        for i in 0..count {
+            let unit = WorkUnit {
+                bases,
+                dec_length,
+                bin_length,
+                prefix_digits,
+                first_prefix: first + num_prefixes * i / count,
+                last_prefix: first + num_prefixes * (i + 1) / count - 1,
+            };
+            unit.validate()?;
+            units.push(unit);
+        }
+    }
+
+    Ok(units)
+}
+
+/// Runs `units` one after another, sharing the tables between units of the same decimal length,
+/// and passes each result to `on_result` as soon as it's done.
+pub fn run(
+    units: &[WorkUnit],
+    config: &SearchConfig,
+    mut on_result: impl FnMut(UnitResult),
+) -> Result<(), String> {
+    for unit in units {
+        unit.validate()?;
+    }
+
+    for group in units.chunk_by(|a, b| a.bases == b.bases && a.dec_length == b.dec_length) {
+        let (bases, dec_length) = (group[0].bases, group[0].dec_length);
+        with_uint!(bases.required_bits(dec_length), T => {
+            run_group::<T>(group, config, &mut on_result)
+        });
+    }
+
+    Ok(())
+}
+
+fn run_group<T: UInt>(
+    units: &[WorkUnit],
+    config: &SearchConfig,
+    on_result: &mut impl FnMut(UnitResult),
+) {
+    let searcher = UnitSearcher::<T>::new(units[0].bases, units[0].dec_length, config);
+    for unit in units {
+        let unit_start = Instant::now();
+        let save_state = Mutex::new(unit_save_state(unit));
+        let progress = Progress::default();
+        searcher.search(unit, None, config, &save_state, &progress);
+
+        let mut palindromes = save_state.into_inner().unwrap().palindromes_found;
+        palindromes.sort_unstable();
+        on_result(UnitResult {
+            unit: unit.clone(),
+            palindromes,
+            nodes: progress.nodes.into_inner(),
+            seconds: unit_start.elapsed().as_secs_f32(),
+        });
+    }
+}
+
+/// A save state for searching only `unit`, which collects what it finds and, if stopped, the
+/// stacks it has left.
+pub fn unit_save_state(unit: &WorkUnit) -> SaveState {
+    SaveState::new(
+        unit.bases,
+        unit.dec_length,
+        Some(unit.dec_length),
+        Some(vec![unit.bin_length]),
+    )
+}
+
+/// The tables for searching units of one decimal length, kept between units since they're
+/// expensive to generate.
+pub struct UnitSearcher<T: UInt> {
+    bases: Bases,
+    dec_length: u32,
+    layout: BaseLayout<T>,
+    digit_cache: Vec<Vec<T>>,
+    max_dec_cache: Vec<T>,
+    lookup_table: LookupTable,
+    remainder_tables: RemainderTables,
+    start_time: Instant,
+}
+
+impl<T: UInt> UnitSearcher<T> {
+    /// Generates the tables for a decimal length, as the search would.
+    pub fn new(bases: Bases, dec_length: u32, config: &SearchConfig) -> Self {
+        let start_time = Instant::now();
+        let layout = BaseLayout::<T>::new(bases, dec_length);
+        let (min_bin_length, max_bin_length) = bin_length_range(&layout, dec_length);
+        let digit_cache = get_digit_cache(dec_length, bases.outer);
+        let max_dec_cache = get_max_cache::<T>(dec_length, bases.outer);
+        let (lookup_table, remainder_tables) = generate_tables(
+            dec_length,
+            bases,
+            &layout,
+            &digit_cache,
+            min_bin_length,
+            max_bin_length,
+            config,
+            start_time,
+        );
+
+        Self {
+            bases,
+            dec_length,
+            layout,
+            digit_cache,
+            max_dec_cache,
+            lookup_table,
+            remainder_tables,
+            start_time,
+        }
+    }
+
+    /// Whether the tables are the ones for `unit`.
+    pub fn can_search(&self, unit: &WorkUnit) -> bool {
+        unit.bases == self.bases && unit.dec_length == self.dec_length
+    }
+
+    /// Searches `unit` from its prefixes, or from `stack` if an earlier search of it was stopped.
+    pub fn search(
+        &self,
+        unit: &WorkUnit,
+        stack: Option<&[State<Widest>]>,
+        config: &SearchConfig,
+        save_state: &Mutex<SaveState>,
+        progress: &Progress,
+    ) {
+        let max_bin_cache =
+            get_max_bin_cache(&self.layout, unit.bin_length, self.digit_cache.len());
+        let stack = match stack {
+            Some(stack) => stack.iter().map(State::from_widest).collect(),
+            None => prefix_states(
+                unit,
+                &self.layout,
+                &self.digit_cache,
+                &self.max_dec_cache,
+                &max_bin_cache,
+            ),
+        };
+        rayon::scope(|scope| {
+            find_palindrome_recursive(
+                stack,
+                self.dec_length,
+                unit.bin_length,
+                &self.layout,
+                &self.digit_cache,
+                &self.max_dec_cache,
+                &max_bin_cache,
+                &self.lookup_table,
+                &self.remainder_tables,
+                config,
+                self.start_time,
+                scope,
+                save_state,
+                progress,
+            );
+        });
+    }
+}
+
+// The states which the search would reach after fixing each prefix in the unit's range, without
+// the ones it would have pruned right away.
+fn prefix_states<T: UInt>(
+    unit: &WorkUnit,
+    layout: &BaseLayout<T>,
+    digit_cache: &[Vec<T>],
+    max_dec_cache: &[T],
+    max_bin_cache: &[T],
+) -> Vec<State<T>> {
+    let initial = initial_state(layout);
+    let level = unit.prefix_digits;
+    if level == 0 {
+        return vec![initial];
+    }
+
+    let outer = layout.outer as u64;
+    let mut states: Vec<State<T>> = (unit.first_prefix..=unit.last_prefix)
+        .rev()
+        .filter_map(|prefix| {
+            let mut current_num = T::ZERO;
+            let mut rest = prefix;
+            let mut leading_digit = 0;
+            for digit_level in (0..level).rev() {
+                leading_digit = rest % outer;
+                current_num += digit_cache[digit_level as usize][leading_digit as usize];
+                rest /= outer;
+            }
+            if initial.is_odd == Some(true) && leading_digit % 2 == 0 {
+                return None;
+            }
+
+            let mut bin_num = T::ZERO;
+            for position in 0..layout.fixed_digits[level as usize] {
+                bin_num += layout.mirror_digit(
+                    layout.digit(current_num, position),
+                    position,
+                    unit.bin_length,
+                );
+            }
+
+            let parent = level as usize - 1;
+            if bin_num + max_bin_cache[parent] < current_num
+                || current_num + max_dec_cache[parent] < bin_num
+            {
+                return None;
+            }
+
+            // Leaving the parity open only costs some pruning.
+            Some(State {
+                current_num,
+                bin_num,
+                is_odd: None,
+                level,
+                share: 0.0,
+            })
+        })
+        .collect();
+    // Units only count their nodes, so their states just split the unit evenly.
+    let share = 1.0 / states.len() as f64;
+    for state in &mut states {
+        state.share = share;
+    }
+
+    states
+}
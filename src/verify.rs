@@ -0,0 +1,450 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt, fs,
+    path::Path,
+};
+
+use serde_json::Value;
+
+use crate::bases::Bases;
+
+/// A palindrome listed in a search's output.
+pub struct Entry {
+    /// Where it's listed to point at it in reports, or its position in a checkpoint.
+    pub line: usize,
+    /// As written, in base 10.
+    pub value: String,
+}
+
+/// What a search printed, in either output format.
+#[derive(Default)]
+pub struct Results {
+    /// The palindromes listed, in order.
+    pub entries: Vec<Entry>,
+    /// Decimal lengths the output says were finished.
+    pub finished: BTreeSet<u32>,
+}
+
+/// Reads a search's output, as "time: number" lines or JSON lines.
+///
+/// Text lines which aren't palindromes or finished decimal lengths, like the verbose ones, are
+/// skipped.
+pub fn read_results(path: &Path) -> Result<Results, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut results = Results::default();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('{') {
+            read_json_line(line, index + 1, &mut results)?;
+        } else {
+            read_text_line(line, index + 1, &mut results);
+        }
+    }
+
+    Ok(results)
+}
+
+fn read_json_line(line: &str, number: usize, results: &mut Results) -> Result<(), String> {
+    let event: Value =
+        serde_json::from_str(line).map_err(|err| format!("Invalid line {number}: {err}"))?;
+    match event["event"].as_str() {
+        Some("found") => {
+            let value = match &event["value"] {
+                Value::String(value) => value.clone(),
+                // Invalid, but the check should say so rather than drop it.
+                value => value.to_string(),
+            };
+            results.entries.push(Entry {
+                line: number,
+                value,
+            });
+        }
+        Some("length_finished") => {
+            if let Some(dec_length) = event["dec_length"].as_u64() {
+                results.finished.insert(dec_length as u32);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn read_text_line(line: &str, number: usize, results: &mut Results) {
+    let Some((time, rest)) = line.split_once(": ") else {
+        return;
+    };
+    if time.parse::<f64>().is_err() {
+        return;
+    }
+
+    if rest.starts_with(|c: char| c.is_ascii_digit()) {
+        results.entries.push(Entry {
+            line: number,
+            value: rest.trim().to_string(),
+        });
+        return;
+    }
+    // Older versions printed "Finished decimal length" for every binary length, which doesn't mean
+    // the decimal length is done.
+    if let Some(dec_length) = rest
+        .strip_prefix("Finished decimal length ")
+        .and_then(|rest| rest.split_once(", expanded"))
+        .and_then(|(dec_length, _)| dec_length.parse().ok())
+    {
+        results.finished.insert(dec_length);
+    }
+}
+
+// The search's integers are what's being checked, so numbers are kept as digits and converted by
+// plain long division instead.
+
+// The digits of a number in base `to`, least significant first, from its digits in base `from`,
+// most significant first.
+fn convert(digits: &[u32], from: u32, to: u32) -> Vec<u32> {
+    // Divides by the highest power of `to` that can't overflow, for that many digits at once.
+    let (from, to) = (from as u64, to as u64);
+    let (mut divisor, mut digits_per_division) = (to, 1);
+    while let Some(next) = divisor.checked_mul(to * from) {
+        divisor = next / from;
+        digits_per_division += 1;
+    }
+
+    let mut num: Vec<u64> = digits.iter().map(|&digit| digit as u64).collect();
+    // Leading zeros of the quotient.
+    let mut start = 0;
+    let mut converted = vec![];
+    while start < num.len() {
+        let mut remainder = 0;
+        for digit in &mut num[start..] {
+            let current = remainder * from + *digit;
+            *digit = current / divisor;
+            remainder = current % divisor;
+        }
+        while start < num.len() && num[start] == 0 {
+            start += 1;
+        }
+        if start < num.len() {
+            for _ in 0..digits_per_division {
+                converted.push((remainder % to) as u32);
+                remainder /= to;
+            }
+        } else {
+            // The most significant digits, without leading zeros.
+            while remainder > 0 {
+                converted.push((remainder % to) as u32);
+                remainder /= to;
+            }
+        }
+    }
+
+    converted
+}
+
+fn decimal_digits(decimal: &str) -> Vec<u32> {
+    decimal.bytes().map(|byte| (byte - b'0') as u32).collect()
+}
+
+fn to_decimal(digits: &[u32], base: u32) -> String {
+    convert(digits, base, 10)
+        .iter()
+        .rev()
+        .map(|&digit| char::from_digit(digit, 10).unwrap())
+        .collect()
+}
+
+fn is_palindrome(digits: &[u32]) -> bool {
+    digits.iter().eq(digits.iter().rev())
+}
+
+// Every number which has `length` digits in the outer base and is a palindrome in both bases.
+fn enumerate(bases: Bases, length: u32) -> Vec<String> {
+    let half_length = length.div_ceil(2) as usize;
+    // The first half, most significant first, counted up like an odometer.
+    let mut half = vec![0; half_length];
+    half[0] = 1;
+    let mut found = vec![];
+    loop {
+        let mirrored = half.iter().rev().skip(length as usize % 2);
+        let digits: Vec<u32> = half.iter().chain(mirrored).copied().collect();
+        if is_palindrome(&convert(&digits, bases.outer, bases.inner)) {
+            found.push(to_decimal(&digits, bases.outer));
+        }
+
+        let Some(position) = half.iter().rposition(|&digit| digit + 1 < bases.outer) else {
+            return found;
+        };
+        half[position] += 1;
+        half[position + 1..].fill(0);
+    }
+}
+
+/// Something wrong with the palindromes listed by a search.
+pub enum Problem {
+    /// An entry which isn't a decimal number without leading zeros.
+    NotANumber {
+        /// Where it's listed.
+        line: usize,
+        /// The entry as written.
+        value: String,
+    },
+    /// A number which isn't a palindrome in one of the bases.
+    NotAPalindrome {
+        /// Where it's listed.
+        line: usize,
+        /// The number in base 10.
+        value: String,
+        /// The bases it should be a palindrome in.
+        bases: Bases,
+    },
+    /// A number listed more than once.
+    Duplicate {
+        /// Where it's listed again.
+        line: usize,
+        /// The number in base 10.
+        value: String,
+        /// The line it was first listed on.
+        first: usize,
+    },
+    /// A palindrome of a complete length which isn't listed.
+    Missing {
+        /// The palindrome in base 10.
+        value: String,
+        /// Its length in outer digits.
+        length: u32,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotANumber { line, value } => write!(f, "{value} on line {line} is not a number"),
+            Self::NotAPalindrome { line, value, bases } => write!(
+                f,
+                "{value} on line {line} is not a palindrome in bases {} and {}",
+                bases.outer, bases.inner
+            ),
+            Self::Duplicate { line, value, first } => {
+                write!(
+                    f,
+                    "{value} on line {line} was already listed on line {first}"
+                )
+            }
+            Self::Missing { value, length } => {
+                write!(f, "{value} is missing from length {length}")
+            }
+        }
+    }
+}
+
+/// Checks palindromes listed by a search for any that aren't a palindrome in both bases or are
+/// listed twice, and for any missing from the `complete` lengths up to `enumerate_to`. Lengths
+/// are counted in digits of the outer base.
+///
+/// Returns everything that's wrong, in the order the entries are listed and then by length.
+pub fn verify(
+    entries: &[Entry],
+    bases: Bases,
+    complete: &BTreeSet<u32>,
+    enumerate_to: u32,
+) -> Vec<Problem> {
+    let mut problems = vec![];
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for &Entry { line, ref value } in entries {
+        if value.is_empty()
+            || !value.bytes().all(|byte| byte.is_ascii_digit())
+            || value.starts_with('0')
+        {
+            problems.push(Problem::NotANumber {
+                line,
+                value: value.clone(),
+            });
+            continue;
+        }
+        let digits = decimal_digits(value);
+        if !is_palindrome(&convert(&digits, 10, bases.outer))
+            || !is_palindrome(&convert(&digits, 10, bases.inner))
+        {
+            problems.push(Problem::NotAPalindrome {
+                line,
+                value: value.clone(),
+                bases,
+            });
+        }
+        if let Some(&first) = seen.get(value.as_str()) {
+            problems.push(Problem::Duplicate {
+                line,
+                value: value.clone(),
+                first,
+            });
+        } else {
+            seen.insert(value, line);
+        }
+    }
+
+    for &length in complete.range(..=enumerate_to) {
+        for palindrome in enumerate(bases, length) {
+            if !seen.contains_key(palindrome.as_str()) {
+                problems.push(Problem::Missing {
+                    value: palindrome,
+                    length,
+                });
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digits_in(num: u128, base: u32) -> Vec<u32> {
+        let mut digits = vec![];
+        let mut rest = num;
+        while rest > 0 {
+            digits.push((rest % base as u128) as u32);
+            rest /= base as u128;
+        }
+        digits
+    }
+
+    fn entries(values: &[&str]) -> Vec<Entry> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| Entry {
+                line: index + 1,
+                value: value.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn convert_matches_native_integers() {
+        let nums = [
+            1,
+            9,
+            10,
+            35,
+            36,
+            255,
+            256,
+            1 << 64,
+            u64::MAX as u128,
+            u128::MAX,
+        ];
+        for num in nums {
+            for (from, to) in [
+                (10, 2),
+                (2, 10),
+                (36, 10),
+                (10, 36),
+                (3, 7),
+                (16, 2),
+                (1000, 3),
+            ] {
+                let mut most_significant_first = digits_in(num, from);
+                most_significant_first.reverse();
+                assert_eq!(
+                    convert(&most_significant_first, from, to),
+                    digits_in(num, to),
+                    "{num} from {from} to {to}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn convert_divides_by_several_digits_at_once() {
+        // 10 to 2 fits many binary digits in a division, so quotients cross several divisions.
+        let decimal = "340282366920938463463374607431768211455";
+        let binary = convert(&decimal_digits(decimal), 10, 2);
+        assert_eq!(binary, vec![1; 128]);
+        assert_eq!(to_decimal(&[1; 128], 2), decimal);
+        let base36 = "zyxwvutsrqponmlkjihgfedcba9876543210";
+        let digits: Vec<u32> = base36.chars().map(|c| c.to_digit(36).unwrap()).collect();
+        let value = to_decimal(&digits, 36);
+        let mut back = convert(&decimal_digits(&value), 10, 36);
+        back.reverse();
+        assert_eq!(back, digits);
+    }
+
+    #[test]
+    fn enumerate_matches_the_sequence() {
+        let found: Vec<String> = (1..=4)
+            .flat_map(|length| enumerate(Bases::default(), length))
+            .collect();
+        assert_eq!(
+            found,
+            ["1", "3", "5", "7", "9", "33", "99", "313", "585", "717", "7447", "9009"]
+        );
+    }
+
+    #[test]
+    fn enumerate_matches_brute_force_in_other_bases() {
+        for (outer, inner) in [(3, 2), (2, 3), (16, 2), (36, 10)] {
+            let bases = Bases { outer, inner };
+            // Brute force tries every number of the length, so bigger bases stop sooner.
+            for length in 1..=100_000u32.ilog(outer) {
+                let low = (outer as u128).pow(length - 1);
+                let expected: Vec<String> = (low..low * outer as u128)
+                    .filter(|&num| {
+                        let outer_digits = digits_in(num, outer);
+                        let inner_digits = digits_in(num, inner);
+                        is_palindrome(&outer_digits) && is_palindrome(&inner_digits)
+                    })
+                    .map(|num| num.to_string())
+                    .collect();
+                assert_eq!(enumerate(bases, length), expected, "{bases:?} {length}");
+            }
+        }
+    }
+
+    #[test]
+    fn valid_entries_have_no_problems() {
+        let complete = BTreeSet::from([1, 2, 3]);
+        let listed = entries(&["1", "3", "5", "7", "9", "33", "99", "313", "585", "717"]);
+        assert!(verify(&listed, Bases::default(), &complete, 3).is_empty());
+    }
+
+    #[test]
+    fn every_problem_is_found() {
+        let listed = entries(&["1", "", "0", "01", "12a", "3", "10", "1"]);
+        let complete = BTreeSet::from([1, 5]);
+        let problems: Vec<String> = verify(&listed, Bases::default(), &complete, 1)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                " on line 2 is not a number",
+                "0 on line 3 is not a number",
+                "01 on line 4 is not a number",
+                "12a on line 5 is not a number",
+                "10 on line 7 is not a palindrome in bases 10 and 2",
+                "1 on line 8 was already listed on line 1",
+                "5 is missing from length 1",
+                "7 is missing from length 1",
+                "9 is missing from length 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn palindromes_in_only_one_base_are_rejected() {
+        // Both are palindromes in base 10 only.
+        let problems = verify(
+            &entries(&["22", "6"]),
+            Bases::default(),
+            &BTreeSet::new(),
+            0,
+        );
+        assert_eq!(problems.len(), 2);
+        assert!(problems
+            .iter()
+            .all(|problem| matches!(problem, Problem::NotAPalindrome { .. })));
+    }
+}
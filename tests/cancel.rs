@@ -0,0 +1,25 @@
+//! Checks that cancelling a search stops only that search.
+
+use std::sync::{atomic::AtomicBool, Arc};
+
+use palindromes::{bases::Bases, run_search, sink::MemorySink, SaveState, SearchConfig};
+
+fn quiet() -> SearchConfig {
+    SearchConfig {
+        sink: Some(Arc::new(MemorySink::default())),
+        ..SearchConfig::default()
+    }
+}
+
+#[test]
+fn cancelled_search_leaves_the_next_one_alone() {
+    let cancelled = SearchConfig {
+        cancel: Some(Arc::new(AtomicBool::new(true))),
+        ..quiet()
+    };
+    let save_state = SaveState::new(Bases::default(), 1, Some(12), None);
+    assert!(!run_search(save_state, &cancelled).unwrap());
+
+    let save_state = SaveState::new(Bases::default(), 1, Some(12), None);
+    assert!(run_search(save_state, &quiet()).unwrap());
+}
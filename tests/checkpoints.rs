@@ -0,0 +1,128 @@
+//! Loads checkpoints written by earlier builds, and checks that damaged ones are rejected.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use palindromes::checkpoint::{self, Loaded, FORMAT_VERSION};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+// A copy of a fixture to damage, with a name of its own since the tests run in parallel.
+fn copy(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("palindromes-{}-{name}", std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn load(name: &str) -> Loaded {
+    checkpoint::load(&fixture(name)).unwrap_or_else(|error| panic!("{name}: {error}"))
+}
+
+fn assert_upgraded(loaded: &Loaded, from: u32) {
+    let note = format!("Upgrading checkpoint from format version {from} to {FORMAT_VERSION}");
+    assert_eq!(loaded.notes, [note]);
+}
+
+#[test]
+fn baseline_checkpoint_loads() {
+    let loaded = load("checkpoint-v1.json");
+    assert_upgraded(&loaded, 1);
+
+    let save_state = &loaded.save_state;
+    assert_eq!(save_state.dec_length, 29);
+    assert_eq!(save_state.palindromes_found.len(), 88);
+    assert_eq!(save_state.tasks.len(), 8);
+    // The baseline always started at length 1, so every earlier length is done.
+    assert_eq!(save_state.completed_lengths(), (1..=28).collect());
+}
+
+#[test]
+fn older_checkpoints_load() {
+    for (name, from, tasks) in [
+        ("checkpoint-v2.json", 2, 12),
+        ("checkpoint-v3.json", 3, 10),
+        ("checkpoint-v4.json", 4, 0),
+        ("checkpoint-v4.bin", 4, 0),
+    ] {
+        let loaded = load(name);
+        assert_upgraded(&loaded, from);
+
+        let save_state = &loaded.save_state;
+        assert_eq!(save_state.dec_length, 30, "{name}");
+        assert_eq!(save_state.palindromes_found.len(), 92, "{name}");
+        assert_eq!(save_state.tasks.len(), tasks, "{name}");
+        assert_eq!(
+            save_state.completed_lengths(),
+            (1..=29).collect::<BTreeSet<_>>(),
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn baseline_and_later_checkpoints_agree() {
+    let baseline = load("checkpoint-v1.json").save_state;
+    let later = load("checkpoint-v4.json").save_state;
+    assert!(later
+        .palindromes_found
+        .starts_with(&baseline.palindromes_found));
+}
+
+#[test]
+fn corrupted_checksum_is_rejected() {
+    let contents = fs::read_to_string(fixture("checkpoint-v3.json")).unwrap();
+    let contents = contents.replace("ab4fad6293a84811", "ab4fad6293a84812");
+    let path = copy("corrupted-checksum.json", contents.as_bytes());
+
+    let error = checkpoint::load(&path)
+        .err()
+        .expect("The checksum is wrong");
+    assert!(error.to_string().contains("Rejected checkpoint"), "{error}");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn corrupted_state_is_rejected() {
+    let contents = fs::read_to_string(fixture("checkpoint-v3.json")).unwrap();
+    let contents = contents.replacen("\"dec_length\":30", "\"dec_length\":31", 1);
+    let path = copy("corrupted-state.json", contents.as_bytes());
+
+    let error = checkpoint::load(&path)
+        .err()
+        .expect("The state was changed");
+    assert!(error.to_string().contains("Rejected checkpoint"), "{error}");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn edited_header_is_rejected() {
+    let path = copy("edited-header.json", b"");
+    checkpoint::write(
+        &path,
+        &load("checkpoint-v4.json").save_state,
+        checkpoint::CheckpointFormat::Json,
+        0,
+    )
+    .unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    let edited = contents.replacen(
+        &format!("\"build\":\"{}\"", env!("CARGO_PKG_VERSION")),
+        "\"build\":\"0.0.1\"",
+        1,
+    );
+    assert_ne!(edited, contents);
+    fs::write(&path, edited).unwrap();
+
+    let error = checkpoint::load(&path)
+        .err()
+        .expect("The header was edited");
+    assert!(error.to_string().contains("Rejected checkpoint"), "{error}");
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,45 @@
+//! Checks that mistakes on the command line end with a message and the documented exit status
+//! rather than a panic.
+
+use std::process::{Command, Output};
+
+fn palindromes(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_palindromes"))
+        .args(args)
+        .output()
+        .expect("Error running palindromes")
+}
+
+fn assert_fails(args: &[&str], status: i32, message: &str) {
+    let output = palindromes(args);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(status), "{stderr}");
+    assert!(stderr.contains(message), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn zero_length_is_rejected() {
+    assert_fails(&["search", "--length", "0"], 2, "--length");
+}
+
+#[test]
+fn invalid_bases_fail() {
+    assert_fails(&["search", "--outer", "1"], 1, "Invalid bases");
+}
+
+#[test]
+fn too_wide_search_fails() {
+    assert_fails(&["search", "--from", "400"], 1, "decimal length 400");
+}
+
+#[test]
+fn missing_checkpoint_fails() {
+    let path =
+        std::env::temp_dir().join(format!("palindromes-{}-missing.json", std::process::id()));
+    assert_fails(
+        &["resume", path.to_str().unwrap()],
+        1,
+        "Error loading checkpoint",
+    );
+}
@@ -0,0 +1,197 @@
+//! Runs a coordinator and several workers on localhost, interrupting one worker and killing
+//! another, and checks that they still find what a single search does.
+#![cfg(unix)]
+
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    io::{BufRead, BufReader},
+    process::{Child, ChildStdout, Command, Output, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+const LENGTH: &str = "29";
+// The exit status of an interrupted worker.
+const EXIT_INTERRUPTED: i32 = 3;
+
+fn palindromes() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_palindromes"))
+}
+
+fn run(command: &mut Command) -> Output {
+    let output = command.output().expect("Error running palindromes");
+    assert!(output.status.success(), "{output:?}");
+    output
+}
+
+// The numbers in "time: number" lines.
+fn found(line: &str) -> Option<String> {
+    let (time, value) = line.split_once(": ")?;
+    time.parse::<f64>().ok()?;
+    value
+        .bytes()
+        .all(|byte| byte.is_ascii_digit())
+        .then(|| value.to_string())
+}
+
+fn worker(address: &str) -> Child {
+    palindromes()
+        .args(["work", "--connect", address, "--threads", "1"])
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Error starting worker")
+}
+
+// Passes the coordinator's lines on, so the test can wait for them.
+fn forward_lines(stdout: ChildStdout) -> mpsc::Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if sender.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+#[test]
+fn workers_which_stop_leave_nothing_out() {
+    let search = run(palindromes().args(["search", "--length", LENGTH]));
+    let expected: BTreeSet<String> = String::from_utf8(search.stdout)
+        .unwrap()
+        .lines()
+        .filter_map(found)
+        .collect();
+    assert!(!expected.is_empty());
+
+    let units = run(palindromes().args(["export-units", "--length", LENGTH, "--units", "4"]));
+    let units_path =
+        env::temp_dir().join(format!("palindromes-cluster-{}.jsonl", std::process::id()));
+    fs::write(&units_path, units.stdout).unwrap();
+
+    let mut coordinator = palindromes()
+        .arg("coordinate")
+        .arg(&units_path)
+        .args(["--listen", "127.0.0.1:0", "--verbose"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Error starting coordinator");
+    let lines = forward_lines(coordinator.stdout.take().unwrap());
+    let address = lines
+        .recv()
+        .unwrap()
+        .strip_prefix("Listening on ")
+        .expect("The coordinator should say where it listens")
+        .to_string();
+
+    let mut interrupted = worker(&address);
+    let mut killed = worker(&address);
+    let mut survivor = worker(&address);
+    let mut output = vec![];
+    let mut connected = 0;
+    while connected < 3 {
+        let line = lines.recv().unwrap();
+        if line.starts_with("Worker connected") {
+            connected += 1;
+        }
+        output.push(line);
+    }
+    // Long enough for every worker to be in the middle of a unit.
+    thread::sleep(Duration::from_secs(1));
+
+    let sigint = Command::new("kill")
+        .args(["-INT", &interrupted.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(sigint.success());
+    killed.kill().unwrap();
+    assert_eq!(interrupted.wait().unwrap().code(), Some(EXIT_INTERRUPTED));
+    killed.wait().unwrap();
+
+    assert!(survivor.wait().unwrap().success());
+    assert!(coordinator.wait().unwrap().success());
+    output.extend(lines);
+    fs::remove_file(&units_path).unwrap();
+
+    // Both stopped workers had a unit, either handed back or lost with them.
+    let reissued = output
+        .iter()
+        .filter(|line| line.contains("Reissuing unit"))
+        .count();
+    assert!(reissued >= 2, "{output:?}");
+
+    let found: Vec<String> = output.iter().filter_map(|line| found(line)).collect();
+    // The coordinator reports every palindrome once, however many workers found it.
+    assert_eq!(found.len(), expected.len(), "{found:?}");
+    assert_eq!(found.into_iter().collect::<BTreeSet<_>>(), expected);
+}
+
+// Exports units of a short length to a file of their own, and starts a coordinator for them.
+fn short_coordinator(name: &str, args: &[&str]) -> (Child, mpsc::Receiver<String>, String) {
+    let units = run(palindromes().args(["export-units", "--length", "16", "--units", "3"]));
+    let units_path = env::temp_dir().join(format!(
+        "palindromes-cluster-{}-{name}.jsonl",
+        std::process::id()
+    ));
+    fs::write(&units_path, units.stdout).unwrap();
+
+    let mut coordinator = palindromes()
+        .arg("coordinate")
+        .arg(&units_path)
+        .args(["--listen", "127.0.0.1:0"])
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Error starting coordinator");
+    let lines = forward_lines(coordinator.stdout.take().unwrap());
+    let first = lines.recv().unwrap();
+    fs::remove_file(&units_path).unwrap();
+
+    (coordinator, lines, first)
+}
+
+#[test]
+fn coordinator_prints_json() {
+    let search = run(palindromes().args(["search", "--length", "16"]));
+    let expected: BTreeSet<String> = String::from_utf8(search.stdout)
+        .unwrap()
+        .lines()
+        .filter_map(found)
+        .collect();
+
+    let (mut coordinator, lines, first) = short_coordinator("json", &["--format", "json"]);
+    let listening: serde_json::Value = serde_json::from_str(&first).unwrap();
+    assert_eq!(listening["event"], "listening");
+    let address = listening["address"].as_str().unwrap();
+    assert!(worker(address).wait().unwrap().success());
+    assert!(coordinator.wait().unwrap().success());
+
+    let mut found = BTreeSet::new();
+    let mut left = None;
+    for line in lines {
+        let event: serde_json::Value = serde_json::from_str(&line).expect(&line);
+        match event["event"].as_str().unwrap() {
+            "found" => assert!(found.insert(event["value"].as_str().unwrap().to_string())),
+            "unit_finished" => left = event["left"].as_u64(),
+            _ => {}
+        }
+    }
+    assert_eq!(found, expected);
+    assert_eq!(left, Some(0));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn coordinator_fails_when_it_cannot_write_results() {
+    let (mut coordinator, _lines, first) =
+        short_coordinator("full", &["--verbose", "--results", "/dev/full"]);
+    let address = first.strip_prefix("Listening on ").unwrap();
+    assert!(worker(address).wait().unwrap().success());
+    // It stops handing out units after the first one whose result it couldn't write.
+    assert!(!coordinator.wait().unwrap().success());
+}
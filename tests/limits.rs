@@ -0,0 +1,40 @@
+//! Checks that searches past the widest supported integer end with an error instead of a panic.
+
+use std::sync::Arc;
+
+use palindromes::{bases::Bases, run_search, sink::MemorySink, SaveState, SearchConfig};
+
+fn quiet() -> SearchConfig {
+    SearchConfig {
+        sink: Some(Arc::new(MemorySink::default())),
+        ..SearchConfig::default()
+    }
+}
+
+#[test]
+fn first_length_too_wide_is_rejected() {
+    let error = run_search(SaveState::new(Bases::default(), 400, None, None), &quiet())
+        .expect_err("A decimal length of 400 doesn't fit");
+    assert!(error.contains("decimal length 400"), "{error}");
+}
+
+#[test]
+fn last_length_too_wide_is_rejected() {
+    let error = run_search(
+        SaveState::new(Bases::default(), 1, Some(400), None),
+        &quiet(),
+    )
+    .expect_err("A decimal length of 400 doesn't fit");
+    assert!(error.contains("decimal length 400"), "{error}");
+}
+
+#[test]
+fn unbounded_search_stops_at_the_widest_length() {
+    // Only a binary length which none of these decimal lengths have, so they're all skipped.
+    let save_state = SaveState::new(Bases::default(), 300, None, Some(vec![5]));
+    let error = run_search(save_state, &quiet()).expect_err("The search can't go on forever");
+    assert!(
+        error.contains("Stopped after decimal length 308"),
+        "{error}"
+    );
+}
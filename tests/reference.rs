@@ -0,0 +1,308 @@
+//! Compares the search with a brute-force enumeration of the palindromes, so anything the tables
+//! prune by mistake shows up as missing.
+
+use std::sync::{Arc, Mutex, OnceLock};
+
+use ethnum::u256;
+use rayon::prelude::*;
+
+use palindromes::{
+    bases::Bases,
+    progress::Progress,
+    run_search,
+    sink::MemorySink,
+    uint::{UInt, Widest, U1024, U512},
+    unit::{self, UnitSearcher},
+    SaveState, SearchConfig,
+};
+
+// Release builds reach 20 digits, the first length which needs 128-bit integers. Brute force takes
+// ten times as long for every two more digits, so unoptimized builds stop sooner and the ignored
+// test goes further.
+const MAX_LENGTH: u32 = if cfg!(debug_assertions) { 14 } else { 20 };
+const MAX_IGNORED_LENGTH: u32 = 22;
+const MAX_OTHER_HALVES: u64 = if cfg!(debug_assertions) {
+    100_000
+} else {
+    1_000_000
+};
+// Inner bases which are powers of two, powers of the outer base, share some of its prime factors
+// or none, and outer bases smaller than the inner ones.
+const OTHER_BASES: [(u32, u32); 15] = [
+    (3, 2),
+    (2, 3),
+    (16, 2),
+    (8, 4),
+    (4, 8),
+    (10, 3),
+    (5, 7),
+    (2, 10),
+    (9, 3),
+    (3, 9),
+    (6, 4),
+    (12, 18),
+    (10, 16),
+    (7, 49),
+    (36, 10),
+];
+
+// Every number with `length` decimal digits which is also a binary palindrome, by mirroring every
+// possible first half like mirrored.py. The first half is split into a high and a low part, so the
+// inner loop only adds the low part and its mirror image from a table.
+fn reference_length(length: u32) -> Vec<u128> {
+    let half_length = length.div_ceil(2);
+    let low_length = half_length / 2;
+    let high_length = half_length - low_length;
+    // The mirror image leaves out the middle digit of odd lengths.
+    let mirror_modulus = 10u128.pow(length / 2);
+    let reverse = |mut rest: u128, digits: u32| {
+        let mut reversed = 0;
+        for _ in 0..digits {
+            reversed = reversed * 10 + rest % 10;
+            rest /= 10;
+        }
+        reversed
+    };
+    let low_parts: Vec<u128> = (0..10u128.pow(low_length))
+        .map(|low| {
+            let mirror = reverse(low, low_length) * 10u128.pow(high_length) % mirror_modulus;
+            low * mirror_modulus + mirror
+        })
+        .collect();
+
+    let high_step = 10u128.pow(high_length - 1);
+    // Even numbers end with a 0 in binary, so only odd leading digits can make palindromes there.
+    (1..10)
+        .step_by(2)
+        .flat_map(|leading_digit| leading_digit * high_step..(leading_digit + 1) * high_step)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .flat_map_iter(|high| {
+            let high_part = high * 10u128.pow(low_length) * mirror_modulus
+                + reverse(high, high_length) % mirror_modulus;
+            let mut palindromes = vec![];
+            for low_part in &low_parts {
+                let num = high_part + low_part;
+                if is_binary_palindrome(num) {
+                    palindromes.push(num);
+                }
+            }
+            palindromes
+        })
+        .collect()
+}
+
+fn is_binary_palindrome(num: u128) -> bool {
+    // Comparing the lowest byte with the highest first rules out most numbers with less work.
+    let lowest_byte_matches =
+        num < 1 << 8 || (num >> (120 - num.leading_zeros())) as u8 == (num as u8).reverse_bits();
+    lowest_byte_matches && num.reverse_bits() >> num.leading_zeros() == num
+}
+
+// Every number with `length` digits in the outer base which is also a palindrome in the inner
+// base, without any of the shortcuts above.
+fn reference_in(bases: Bases, length: u32) -> Vec<u128> {
+    let outer = bases.outer as u128;
+    let half_length = length.div_ceil(2);
+    (outer.pow(half_length - 1)..outer.pow(half_length))
+        .filter_map(|half| {
+            let mut num = half;
+            let mut rest = if length % 2 == 1 { half / outer } else { half };
+            while rest > 0 {
+                num = num * outer + rest % outer;
+                rest /= outer;
+            }
+            is_palindrome_in(num, bases.inner).then_some(num)
+        })
+        .collect()
+}
+
+fn is_palindrome_in(num: u128, base: u32) -> bool {
+    let mut reversed = 0;
+    let mut rest = num;
+    while rest > 0 {
+        reversed = reversed * base as u128 + rest % base as u128;
+        rest /= base as u128;
+    }
+    reversed == num
+}
+
+fn reference(lengths: impl Iterator<Item = u32>) -> Vec<Widest> {
+    let mut palindromes: Vec<Widest> = lengths
+        .flat_map(reference_length)
+        .map(Widest::from)
+        .collect();
+    palindromes.sort();
+    palindromes
+}
+
+fn search(bases: Bases, max_length: u32, config: SearchConfig) -> Vec<Widest> {
+    let sink = Arc::new(MemorySink::default());
+    let config = SearchConfig {
+        sink: Some(sink.clone()),
+        ..config
+    };
+    assert!(run_search(SaveState::new(bases, 1, Some(max_length), None), &config).unwrap());
+
+    let mut palindromes = sink.palindromes();
+    palindromes.sort();
+    palindromes
+}
+
+// Searches every binary length of `dec_length` as a unit on `T`, which the search would only pick
+// for lengths too wide to brute force.
+fn search_at_width<T: UInt>(bases: Bases, dec_length: u32) -> Vec<Widest> {
+    let config = SearchConfig {
+        sink: Some(Arc::new(MemorySink::default())),
+        ..SearchConfig::default()
+    };
+    let searcher = UnitSearcher::<T>::new(bases, dec_length, &config);
+    let mut palindromes = vec![];
+    for unit in unit::export(bases, dec_length, None, 0, 1).unwrap() {
+        let save_state = Mutex::new(unit::unit_save_state(&unit));
+        searcher.search(&unit, None, &config, &save_state, &Progress::default());
+        palindromes.extend(save_state.into_inner().unwrap().palindromes_found);
+    }
+
+    palindromes.sort();
+    palindromes
+}
+
+fn check(expected: &[Widest], max_length: u32, config: SearchConfig) {
+    check_in(Bases::default(), expected, max_length, config);
+}
+
+fn check_in(bases: Bases, expected: &[Widest], max_length: u32, config: SearchConfig) {
+    let found = search(bases, max_length, config);
+    let missing: Vec<String> = expected
+        .iter()
+        .filter(|palindrome| found.binary_search(palindrome).is_err())
+        .map(ToString::to_string)
+        .collect();
+    assert!(missing.is_empty(), "{bases:?} missing {missing:?}");
+    // Anything else is either not a palindrome or found twice.
+    assert_eq!(found, expected, "{bases:?}");
+}
+
+fn up_to_max_length() -> &'static [Widest] {
+    static REFERENCE: OnceLock<Vec<Widest>> = OnceLock::new();
+    REFERENCE.get_or_init(|| reference(1..=MAX_LENGTH))
+}
+
+fn without_tables() -> SearchConfig {
+    SearchConfig {
+        // Too little for any lookup or remainder tables.
+        memory_budget: Some(1),
+        ..SearchConfig::default()
+    }
+}
+
+fn only_remainder_tables() -> SearchConfig {
+    SearchConfig {
+        max_table_digits: Some(0),
+        ..SearchConfig::default()
+    }
+}
+
+fn downscaled_tables(min_downscale_factor: u32) -> SearchConfig {
+    SearchConfig {
+        min_downscale_factor,
+        ..SearchConfig::default()
+    }
+}
+
+#[test]
+fn reference_starts_like_the_sequence() {
+    // A007632 without 0, which the search doesn't report either.
+    let expected = [1, 3, 5, 7, 9, 33, 99, 313, 585, 717, 7447, 9009];
+    let palindromes: Vec<u128> = (1..=4).flat_map(reference_length).collect();
+    let mut sorted = palindromes.clone();
+    sorted.sort();
+    assert_eq!(sorted, expected);
+}
+
+#[test]
+fn search_without_tables() {
+    check(up_to_max_length(), MAX_LENGTH, without_tables());
+}
+
+#[test]
+fn search_with_only_remainder_tables() {
+    check(up_to_max_length(), MAX_LENGTH, only_remainder_tables());
+}
+
+#[test]
+fn search_with_tables() {
+    check(up_to_max_length(), MAX_LENGTH, SearchConfig::default());
+}
+
+#[test]
+fn search_with_downscaled_tables() {
+    for min_downscale_factor in [1, 3, 6] {
+        check(
+            up_to_max_length(),
+            MAX_LENGTH,
+            downscaled_tables(min_downscale_factor),
+        );
+    }
+}
+
+#[test]
+fn search_other_bases() {
+    for (outer, inner) in OTHER_BASES {
+        let bases = Bases { outer, inner };
+        // As long as brute force has at most MAX_OTHER_HALVES first halves to mirror.
+        let max_length = 2 * MAX_OTHER_HALVES.ilog(outer as u64);
+        let mut expected: Vec<Widest> = (1..=max_length)
+            .flat_map(|length| reference_in(bases, length))
+            .map(Widest::from)
+            .collect();
+        expected.sort();
+        for config in [
+            without_tables(),
+            only_remainder_tables(),
+            SearchConfig::default(),
+        ] {
+            check_in(bases, &expected, max_length, config);
+        }
+    }
+}
+
+#[test]
+fn search_at_every_width() {
+    for (outer, inner, max_length) in [(10, 2, 12), (3, 2, 16), (10, 3, 8), (16, 2, 6), (7, 49, 6)]
+    {
+        let bases = Bases { outer, inner };
+        for dec_length in 1..=max_length {
+            let mut expected: Vec<Widest> = reference_in(bases, dec_length)
+                .into_iter()
+                .map(Widest::from)
+                .collect();
+            expected.sort();
+            for (width, found) in [
+                (64, search_at_width::<u64>(bases, dec_length)),
+                (128, search_at_width::<u128>(bases, dec_length)),
+                (256, search_at_width::<u256>(bases, dec_length)),
+                (512, search_at_width::<U512>(bases, dec_length)),
+                (1024, search_at_width::<U1024>(bases, dec_length)),
+            ] {
+                assert_eq!(found, expected, "{bases:?} at {dec_length} on {width} bits");
+            }
+        }
+    }
+}
+
+#[test]
+#[ignore = "brute force takes most of an hour on one core, run with --release"]
+fn search_up_to_twenty_two_digits() {
+    let expected = reference(1..=MAX_IGNORED_LENGTH);
+    for config in [
+        without_tables(),
+        only_remainder_tables(),
+        SearchConfig::default(),
+        downscaled_tables(3),
+        downscaled_tables(6),
+    ] {
+        check(&expected, MAX_IGNORED_LENGTH, config);
+    }
+}
@@ -0,0 +1,79 @@
+//! Checks that a sink which can't write stops the search with an error, and that warnings reach
+//! the sink instead of being printed.
+
+use std::{
+    fs,
+    io::{self, Write},
+    sync::{mpsc, Arc},
+};
+
+use palindromes::{
+    bases::Bases,
+    checkpoint,
+    events::Event,
+    run_search,
+    sink::{ChannelSink, JsonLinesSink},
+    SaveState, SearchConfig,
+};
+
+// Fails every write, like stdout piped into something which exited.
+struct Closed;
+
+impl Write for Closed {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn failing_sink_stops_the_search() {
+    let path = std::env::temp_dir().join(format!("palindromes-{}-closed.json", std::process::id()));
+    let config = SearchConfig {
+        checkpoint: Some(path.clone()),
+        backups: 0,
+        sink: Some(Arc::new(JsonLinesSink::new(Closed))),
+        ..SearchConfig::default()
+    };
+    let error = run_search(SaveState::new(Bases::default(), 1, Some(12), None), &config)
+        .expect_err("Nothing could be reported");
+    assert!(error.starts_with("Error reporting events"), "{error}");
+
+    // The search stopped at the first event, and is resumed from there.
+    let save_state = checkpoint::load(&path).unwrap().save_state;
+    assert_eq!(save_state.dec_length, 1);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn b_file_ahead_of_the_search_is_a_warning() {
+    let path = std::env::temp_dir().join(format!("palindromes-{}-ahead.b", std::process::id()));
+    fs::write(&path, "1 0\n2 1\n3 3\n4 5\n5 7\n6 9\n7 33\n").unwrap();
+    let mut save_state = SaveState::new(Bases::default(), 2, Some(2), None);
+    save_state.palindromes_found = [0u32, 1, 3, 5, 7, 9].map(Into::into).to_vec();
+
+    let (sender, receiver) = mpsc::channel();
+    let config = SearchConfig {
+        b_file: Some(path.clone()),
+        sink: Some(Arc::new(ChannelSink::new(sender))),
+        ..SearchConfig::default()
+    };
+    run_search(save_state, &config).unwrap();
+    drop(config);
+
+    let warnings: Vec<String> = receiver
+        .iter()
+        .filter_map(|event| match event {
+            Event::Warning { message } => Some(message),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        warnings,
+        ["33 is in the b-file but not the checkpoint yet, it will be found again"]
+    );
+    fs::remove_file(path).unwrap();
+}